@@ -1,5 +1,6 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
 use redtail::board::{Board, Position};
+use redtail::chess::move_list::MoveList;
 use redtail::movegen::MoveGenerator;
 
 // Test positions for benchmarking
@@ -66,7 +67,7 @@ fn bench_individual_piece_moves(c: &mut Criterion) {
 
     // Use kiwipete position - it has all piece types active
     let mut board = <Position as Board>::new(Some(KIWIPETE));
-    let mut moves = Vec::with_capacity(256);
+    let mut moves = MoveList::new();
 
     group.bench_function("pawns", |b| {
         b.iter(|| {
@@ -52,6 +52,8 @@ pub struct Position {
     pub zobrist_turn_key: u64,
 
     pub hash: u64,
+    /// Zobrist hash of the pawns only, used to index the pawn hash table
+    pub pawn_hash: u64,
     pub material: [i32; 2],
 
     pub file_masks: [u64; 64],
@@ -61,6 +63,10 @@ pub struct Position {
     pub white_passed_pawn_masks: [u64; 64],
     pub black_passed_pawn_masks: [u64; 64],
 
+    /// The squares around a king (plus the ranks in front of it) that are
+    /// inspected by the king safety evaluation, indexed by [color][square]
+    pub king_zone_masks: [[u64; 64]; 2],
+
     pub opponent_attack_map: u64,
 }
 
@@ -76,6 +82,7 @@ pub struct HistoryEntry {
     pub fullmove_number: u32,
     pub occupancies: [u64; 3],
     pub hash: u64,
+    pub pawn_hash: u64,
 }
 
 /// Lightweight position state for copy-make (only the fields that change during a move)
@@ -158,6 +165,7 @@ impl Board for Position {
             zobrist_turn_key: 0,
 
             hash: 0,
+            pawn_hash: 0,
             material: [0, 0],
 
             white_passed_pawn_masks: [0; 64],
@@ -167,6 +175,8 @@ impl Board for Position {
             rank_masks: [0; 64],
             isolated_pawn_masks: [0; 64],
 
+            king_zone_masks: [[0; 64]; 2],
+
             opponent_attack_map: 0,
         };
 
@@ -291,6 +301,8 @@ impl Board for Position {
             self.bitboards[i] = 0;
         }
         self.mailbox = [Piece::Empty; 64];
+        self.material = [0, 0];
+        self.position_stack.clear();
 
         // Set the bitboard positions
         let mut pos = 0;
@@ -407,6 +419,33 @@ impl Board for Position {
             return false;
         }
 
+        self.make_legal_move(m);
+
+        // ensure the king of the side that moved is not in check
+        if m.get_piece() != Piece::WhiteKing && m.get_piece() != Piece::BlackKing {
+            let king_square = utils::get_lsb(
+                self.bitboards[(!self.turn as usize * 6) + (Piece::WhiteKing as usize)],
+            );
+            if king_square >= 64 || self.is_square_attacked(Square::from(king_square), self.turn) {
+                self.unmake_move();
+                return false;
+            }
+        }
+
+        return true;
+    }
+
+    fn unmake_move(&mut self) {
+        // pop the history entry and apply it
+        let history_entry = self.position_stack.pop().unwrap();
+        self.apply_history_entry(history_entry);
+    }
+}
+
+impl Position {
+    /// Makes a move that is known to be legal, e.g. one from `generate_legal_moves` or the
+    /// move picker, without checking whether it leaves the king in check.
+    pub fn make_legal_move(&mut self, m: chess::_move::BitPackedMove) {
         let game_phase_score = self.get_game_phase_score();
 
         // add the move to the history
@@ -427,6 +466,13 @@ impl Board for Position {
         self.hash ^= self.zobrist_piece_keys[m.get_piece() as usize][m.get_from() as usize];
         self.hash ^= self.zobrist_piece_keys[m.get_piece() as usize][m.get_to() as usize];
 
+        let is_pawn_move = m.get_piece() == Piece::WhitePawn || m.get_piece() == Piece::BlackPawn;
+        if is_pawn_move {
+            self.pawn_hash ^=
+                self.zobrist_piece_keys[m.get_piece() as usize][m.get_from() as usize];
+            self.pawn_hash ^= self.zobrist_piece_keys[m.get_piece() as usize][m.get_to() as usize];
+        }
+
         // remove the moving piece
         utils::clear_bit(
             &mut self.bitboards[m.get_piece() as usize],
@@ -456,6 +502,11 @@ impl Board for Position {
                 game_phase_score,
             );
             self.hash ^= self.zobrist_piece_keys[captured_piece as usize][m.get_to() as usize];
+
+            if captured_piece == Piece::WhitePawn || captured_piece == Piece::BlackPawn {
+                self.pawn_hash ^=
+                    self.zobrist_piece_keys[captured_piece as usize][m.get_to() as usize];
+            }
         }
 
         // handle promotions
@@ -471,6 +522,7 @@ impl Board for Position {
                 game_phase_score,
             );
             self.hash ^= self.zobrist_piece_keys[m.get_piece() as usize][m.get_to() as usize];
+            self.pawn_hash ^= self.zobrist_piece_keys[m.get_piece() as usize][m.get_to() as usize];
 
             // add the promoted piece
             utils::set_bit(
@@ -494,7 +546,11 @@ impl Board for Position {
 
         // handle en passant
         if m.is_enpassant() {
-            let en_captured_square = (m.get_to() as u8) + (8 - (self.turn as u8 * 16));
+            let en_captured_square = if self.turn == Color::White {
+                m.get_to() as u8 + 8
+            } else {
+                m.get_to() as u8 - 8
+            };
 
             if get_bit(self.occupancies[2], en_captured_square) != 0 {
                 let en_captured_piece = self.get_piece_at_square(en_captured_square);
@@ -513,6 +569,11 @@ impl Board for Position {
                         game_phase_score,
                     );
 
+                    self.hash ^= self.zobrist_piece_keys[en_captured_piece as usize]
+                        [en_captured_square as usize];
+                    self.pawn_hash ^= self.zobrist_piece_keys[en_captured_piece as usize]
+                        [en_captured_square as usize];
+
                     // update mailbox for en passant capture
                     self.mailbox[en_captured_square as usize] = Piece::Empty;
                 }
@@ -522,7 +583,7 @@ impl Board for Position {
         self.enpassant = None;
 
         // handle setting the en passant square during double pawn pushes
-        if m.get_piece() == Piece::WhitePawn || m.get_piece() == Piece::BlackPawn {
+        if is_pawn_move {
             let offset = -1 * (m.get_from() as i8 - m.get_to() as i8) / 2;
             if offset == 8 || offset == -8 {
                 self.enpassant = Some(Square::from((m.get_from() as i8 + offset) as u8));
@@ -689,28 +750,10 @@ impl Board for Position {
 
         self.update_occupancies();
 
-        // ensure the king is not in check
-        if m.get_piece() != Piece::WhiteKing && m.get_piece() != Piece::BlackKing {
-            if self.is_in_check() {
-                self.unmake_move();
-                return false;
-            }
-        }
-
         self.turn = !self.turn;
         self.hash ^= self.zobrist_turn_key;
-
-        return true;
-    }
-
-    fn unmake_move(&mut self) {
-        // pop the history entry and apply it
-        let history_entry = self.position_stack.pop().unwrap();
-        self.apply_history_entry(history_entry);
     }
-}
 
-impl Position {
     /// Save the current position state for copy-make
     #[inline(always)]
     pub fn save_state(&self) -> PositionState {
@@ -736,8 +779,8 @@ impl Position {
     }
 
     /// Lightweight make_move for perft - skips history stack and material/hash updates
-    /// Returns true if the move is legal, false otherwise
-    pub fn make_move_unchecked(&mut self, m: chess::_move::BitPackedMove) -> bool {
+    /// The move must be legal, e.g. one from `generate_legal_moves`
+    pub fn make_move_unchecked(&mut self, m: chess::_move::BitPackedMove) {
         let piece = m.get_piece();
         let from = m.get_from();
         let to = m.get_to();
@@ -767,7 +810,11 @@ impl Position {
 
         // Handle en passant capture
         if m.is_enpassant() {
-            let en_captured_square = (to as u8) + (8 - (self.turn as u8 * 16));
+            let en_captured_square = if self.turn == Color::White {
+                to as u8 + 8
+            } else {
+                to as u8 - 8
+            };
             let en_captured_piece = if self.turn == Color::White {
                 Piece::BlackPawn
             } else {
@@ -839,13 +886,7 @@ impl Position {
         // Update occupancies
         self.update_occupancies();
 
-        // Check if king is in check (move is illegal)
-        if self.is_in_check() {
-            return false;
-        }
-
         self.turn = !self.turn;
-        true
     }
 
     pub fn set_file_rank_mask(&mut self, file_num: i32, rank_num: i32) -> u64 {
@@ -903,15 +944,28 @@ impl Position {
                 }
             }
         }
+
+        for square in 0..64 {
+            let ring = self.king_attacks[square] | (1u64 << square);
+
+            // White attacks towards rank 8 (lower indices), black towards rank 1
+            self.king_zone_masks[Color::White as usize][square] = ring | (ring >> 8);
+            self.king_zone_masks[Color::Black as usize][square] = ring | (ring << 8);
+        }
     }
 
     pub fn update_hash(&mut self) {
         let mut hash: u64 = 0;
+        let mut pawn_hash: u64 = 0;
         for piece in 0..12 {
             let mut bb = self.bitboards[piece];
             while bb != 0 {
                 let square = pop_lsb(&mut bb);
                 hash ^= self.zobrist_piece_keys[piece][square as usize];
+
+                if piece == Piece::WhitePawn as usize || piece == Piece::BlackPawn as usize {
+                    pawn_hash ^= self.zobrist_piece_keys[piece][square as usize];
+                }
             }
         }
 
@@ -926,10 +980,11 @@ impl Position {
         }
 
         self.hash = hash;
+        self.pawn_hash = pawn_hash;
     }
 
     pub fn init_zorbrist_keys(&mut self) {
-        self.rand_seed = 1804289383;
+        self.rand_seed = constants::ZOBRIST_SEED;
 
         for piece in (Piece::WhitePawn as usize)..=(Piece::BlackKing as usize) {
             for square in 0..64 {
@@ -962,6 +1017,7 @@ impl Position {
             occupancies: self.occupancies,
             material: self.material,
             hash: self.hash,
+            pawn_hash: self.pawn_hash,
         };
     }
 
@@ -996,6 +1052,7 @@ impl Position {
         self.occupancies = entry.occupancies;
         self.material = entry.material;
         self.hash = entry.hash;
+        self.pawn_hash = entry.pawn_hash;
     }
 
     #[inline(always)]
@@ -1444,6 +1501,164 @@ impl Position {
             | self.get_rook_magic_attacks(square, occupancy);
     }
 
+    /// Returns the squares attacked by the given piece standing on the given square,
+    /// using the given occupancy for sliding pieces
+    pub fn get_piece_attacks(&self, piece: Piece, square: Square, occupancy: u64) -> u64 {
+        match piece {
+            Piece::WhitePawn => self.pawn_attacks[Color::White as usize][square as usize],
+            Piece::BlackPawn => self.pawn_attacks[Color::Black as usize][square as usize],
+            Piece::WhiteKnight | Piece::BlackKnight => self.knight_attacks[square as usize],
+            Piece::WhiteBishop | Piece::BlackBishop => {
+                self.get_bishop_magic_attacks(square, occupancy)
+            }
+            Piece::WhiteRook | Piece::BlackRook => self.get_rook_magic_attacks(square, occupancy),
+            Piece::WhiteQueen | Piece::BlackQueen => {
+                self.get_queen_magic_attacks(square, occupancy)
+            }
+            Piece::WhiteKing | Piece::BlackKing => self.king_attacks[square as usize],
+            Piece::Empty => 0,
+        }
+    }
+
+    /// Returns the squares strictly between two squares that share a rank, file or diagonal,
+    /// or an empty bitboard if they are not aligned
+    pub fn get_squares_between(&self, a: Square, b: Square) -> u64 {
+        let a_bit = 1u64 << a as u8;
+        let b_bit = 1u64 << b as u8;
+
+        if self.get_rook_magic_attacks(a, 0) & b_bit != 0 {
+            return self.get_rook_magic_attacks(a, b_bit) & self.get_rook_magic_attacks(b, a_bit);
+        }
+        if self.get_bishop_magic_attacks(a, 0) & b_bit != 0 {
+            return self.get_bishop_magic_attacks(a, b_bit)
+                & self.get_bishop_magic_attacks(b, a_bit);
+        }
+        0
+    }
+
+    /// Returns the pieces of both colors attacking the given square, using the given occupancy
+    /// for sliding pieces so that x-ray attackers are revealed as pieces are removed
+    pub fn get_attackers_to(&self, square: Square, occupancy: u64) -> u64 {
+        let bishops_queens = self.bitboards[Piece::WhiteBishop as usize]
+            | self.bitboards[Piece::BlackBishop as usize]
+            | self.bitboards[Piece::WhiteQueen as usize]
+            | self.bitboards[Piece::BlackQueen as usize];
+        let rooks_queens = self.bitboards[Piece::WhiteRook as usize]
+            | self.bitboards[Piece::BlackRook as usize]
+            | self.bitboards[Piece::WhiteQueen as usize]
+            | self.bitboards[Piece::BlackQueen as usize];
+
+        (self.pawn_attacks[Color::Black as usize][square as usize]
+            & self.bitboards[Piece::WhitePawn as usize])
+            | (self.pawn_attacks[Color::White as usize][square as usize]
+                & self.bitboards[Piece::BlackPawn as usize])
+            | (self.knight_attacks[square as usize]
+                & (self.bitboards[Piece::WhiteKnight as usize]
+                    | self.bitboards[Piece::BlackKnight as usize]))
+            | (self.king_attacks[square as usize]
+                & (self.bitboards[Piece::WhiteKing as usize]
+                    | self.bitboards[Piece::BlackKing as usize]))
+            | (self.get_bishop_magic_attacks(square, occupancy) & bishops_queens)
+            | (self.get_rook_magic_attacks(square, occupancy) & rooks_queens)
+    }
+
+    /// Returns the static exchange evaluation of a move, the material balance for the side to
+    /// move after all the profitable captures and recaptures on the target square are made.
+    pub fn see(&self, m: chess::_move::BitPackedMove) -> i32 {
+        let to = m.get_to();
+        let mut gain = [0i32; 32];
+        let mut depth = 0;
+        let mut occupancy = self.occupancies[2];
+        let mut from_bitboard = 1u64 << m.get_from() as u8;
+        let mut side = self.turn;
+
+        let mut attacker_value = constants::SEE_PIECE_VALUES[m.get_piece() as usize % 6];
+
+        if m.is_enpassant() {
+            gain[0] = constants::SEE_PIECE_VALUES[Piece::WhitePawn as usize];
+            let captured_square = if self.turn == Color::White {
+                to as u8 + 8
+            } else {
+                to as u8 - 8
+            };
+            utils::clear_bit(&mut occupancy, captured_square);
+        } else if m.is_capture() {
+            gain[0] = constants::SEE_PIECE_VALUES[m.get_capture() as usize % 6];
+        }
+
+        if m.is_promotion() {
+            let promotion_value = constants::SEE_PIECE_VALUES[m.get_promotion() as usize % 6];
+            gain[0] += promotion_value - constants::SEE_PIECE_VALUES[Piece::WhitePawn as usize];
+            attacker_value = promotion_value;
+        }
+
+        loop {
+            depth += 1;
+
+            // Speculative score if the piece that just moved to the square is recaptured
+            gain[depth] = attacker_value - gain[depth - 1];
+            if (-gain[depth - 1]).max(gain[depth]) < 0 || depth == gain.len() - 1 {
+                break;
+            }
+
+            occupancy ^= from_bitboard;
+            side = !side;
+
+            let attackers = self.get_attackers_to(to, occupancy) & occupancy;
+            let side_attackers = attackers & self.occupancies[side as usize];
+            if side_attackers == 0 {
+                break;
+            }
+
+            // Recapture with the least valuable attacker
+            let piece_type = (0..6)
+                .find(|&piece_type| {
+                    side_attackers & self.bitboards[piece_type + side as usize * 6] != 0
+                })
+                .unwrap();
+
+            // The king can only recapture if the square is no longer defended
+            if piece_type == Piece::WhiteKing as usize
+                && attackers & self.occupancies[(!side) as usize] != 0
+            {
+                break;
+            }
+
+            let piece_attackers = side_attackers & self.bitboards[piece_type + side as usize * 6];
+            from_bitboard = piece_attackers & piece_attackers.wrapping_neg();
+            attacker_value = constants::SEE_PIECE_VALUES[piece_type];
+        }
+
+        while depth > 1 {
+            depth -= 1;
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+        }
+
+        gain[0]
+    }
+
+    /// Returns true if the static exchange evaluation of a move is at least the given threshold
+    pub fn see_ge(&self, m: chess::_move::BitPackedMove, threshold: i32) -> bool {
+        self.see(m) >= threshold
+    }
+
+    /// Returns every square attacked by the pieces of the given color
+    pub fn get_attacks_by_color(&self, color: Color) -> u64 {
+        let piece_offset = color as usize * 6;
+        let occupancy = self.occupancies[2];
+        let mut attacks = 0u64;
+
+        for piece in piece_offset..(piece_offset + 6) {
+            let mut bitboard = self.bitboards[piece];
+            while bitboard != 0 {
+                let square = Square::from(pop_lsb(&mut bitboard));
+                attacks |= self.get_piece_attacks(Piece::from(piece), square, occupancy);
+            }
+        }
+
+        attacks
+    }
+
     pub fn get_both_occupancy(&self) -> u64 {
         let mut white_occupancy = 0u64;
         let mut black_occupancy = 0u64;
@@ -1500,6 +1715,58 @@ mod tests {
         assert_eq!(position.get_piece_at_square(57), Piece::WhiteKnight);
     }
 
+    #[test]
+    fn incremental_hashes_match_recomputed_hashes() {
+        let mut position = Position::new(Some(
+            "r3k2r/pP1p1ppp/8/2pP4/8/8/P1P2PPP/R3K2R w KQkq c6 0 1",
+        ));
+
+        // en passant, capture promotion, castling and a double pawn push
+        for m in ["d5c6", "e8g8", "b7a8q", "h7h5"] {
+            let parsed = crate::search::utils::parse_move(&mut position, m).unwrap();
+            assert!(position.make_move(parsed, false));
+
+            let (hash, pawn_hash) = (position.hash, position.pawn_hash);
+            position.update_hash();
+            assert_eq!(hash, position.hash, "hash mismatch after {}", m);
+            assert_eq!(
+                pawn_hash, position.pawn_hash,
+                "pawn hash mismatch after {}",
+                m
+            );
+        }
+    }
+
+    #[test]
+    fn static_exchange_evaluation() {
+        let see = |fen: &str, m: &str| {
+            let mut position = Position::new(Some(fen));
+            let parsed = crate::search::utils::parse_move(&mut position, m).unwrap();
+            position.see(parsed)
+        };
+
+        // Undefended pawn
+        assert_eq!(
+            see("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"),
+            100
+        );
+
+        // Queen takes a pawn defended by a pawn
+        assert_eq!(see("4k3/8/3p4/4p3/8/8/4Q3/4K3 w - - 0 1", "e2e5"), -800);
+
+        // Knight takes a pawn defended by a knight, with a bishop x-raying behind the queen
+        assert_eq!(
+            see(
+                "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                "d3e5"
+            ),
+            -220
+        );
+
+        // Quiet move to a square attacked by a pawn
+        assert_eq!(see("4k3/8/3p4/8/8/8/8/3KR3 w - - 0 1", "e1e5"), -500);
+    }
+
     #[ignore]
     #[test]
     fn generate_magic_numbers_correctly() {
@@ -1533,8 +1800,8 @@ mod tests {
     }
 }
 
-const OPENING_GAME_PHASE_SCORE: i32 = 6192;
-const ENDGAME_PHASE_SCORE: i32 = 518;
+pub const OPENING_GAME_PHASE_SCORE: i32 = 6192;
+pub const ENDGAME_PHASE_SCORE: i32 = 518;
 
 const OPENING_PIECE_SCORES: [i32; 12] = [
     82, 337, 365, 477, 1025, 12000, 82, 337, 365, 477, 1025, 12000,
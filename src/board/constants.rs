@@ -3,6 +3,13 @@ pub static BISHOP_RELEVANT_BITS: [u32; 64] = [
     5, 5, 7, 9, 9, 7, 5, 5, 5, 5, 7, 7, 7, 7, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 6, 5, 5, 5, 5, 5, 5, 6,
 ];
 
+/// Piece values used by static exchange evaluation, indexed by piece type
+pub static SEE_PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 20000];
+
+/// Seed of the pseudorandom numbers used for the zobrist keys, positions only hash the same
+/// (e.g. in a saved transposition table) when it does
+pub static ZOBRIST_SEED: u32 = 1804289383;
+
 pub static BLACK_KING_SIDE_CASTLE: u64 = 0x0000000000000060u64;
 pub static BLACK_QUEEN_SIDE_CASTLE: u64 = 0x000000000000000Eu64;
 pub static WHITE_KING_SIDE_CASTLE: u64 = 0x6000000000000000u64;
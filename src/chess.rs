@@ -4,3 +4,4 @@ pub mod square;
 pub mod castling_rights;
 pub mod color;
 pub mod constants;
+pub mod move_list;
@@ -0,0 +1,113 @@
+use std::ops::{Index, IndexMut};
+
+use crate::chess::_move::BitPackedMove;
+
+/// The maximum number of moves a move list can hold, no legal chess position has more than 218.
+pub const MAX_MOVES: usize = 256;
+
+/*
+   A fixed capacity list of moves stored inline, so generating moves during search never
+   touches the heap. Behaves like a `Vec<BitPackedMove>` for the operations move generation
+   and the move picker need.
+*/
+#[derive(Clone, Copy)]
+pub struct MoveList {
+    moves: [BitPackedMove; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> MoveList {
+        MoveList {
+            moves: [BitPackedMove { move_bits: 0 }; MAX_MOVES],
+            len: 0,
+        }
+    }
+
+    /// Appends a move to the end of the list
+    #[inline(always)]
+    pub fn push(&mut self, m: BitPackedMove) {
+        debug_assert!(self.len < MAX_MOVES, "move list overflow");
+        self.moves[self.len] = m;
+        self.len += 1;
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Swaps the moves at the two given indices
+    #[inline(always)]
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.moves.swap(a, b);
+    }
+
+    pub fn contains(&self, m: &BitPackedMove) -> bool {
+        self.as_slice().contains(m)
+    }
+
+    pub fn as_slice(&self) -> &[BitPackedMove] {
+        &self.moves[..self.len]
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, BitPackedMove> {
+        self.as_slice().iter()
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> MoveList {
+        MoveList::new()
+    }
+}
+
+impl Index<usize> for MoveList {
+    type Output = BitPackedMove;
+
+    fn index(&self, index: usize) -> &BitPackedMove {
+        &self.as_slice()[index]
+    }
+}
+
+impl IndexMut<usize> for MoveList {
+    fn index_mut(&mut self, index: usize) -> &mut BitPackedMove {
+        &mut self.moves[..self.len][index]
+    }
+}
+
+impl Extend<BitPackedMove> for MoveList {
+    fn extend<T: IntoIterator<Item = BitPackedMove>>(&mut self, iter: T) {
+        for m in iter {
+            self.push(m);
+        }
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = BitPackedMove;
+    type IntoIter = std::iter::Take<std::array::IntoIter<BitPackedMove, MAX_MOVES>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves.into_iter().take(self.len)
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a BitPackedMove;
+    type IntoIter = std::slice::Iter<'a, BitPackedMove>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
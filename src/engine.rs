@@ -0,0 +1,384 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    board::{Board, Position},
+    chess::{_move::BitPackedMove, constants::STARTING_FEN},
+    search::{
+        constants::DEFAULT_HASH_SIZE_MB,
+        evaluate::Evaluator,
+        listener::{ChannelListener, SearchInfo},
+        options::SearchOptions,
+        utils::parse_move,
+    },
+    tt::TranspositionTable,
+};
+
+/// What a finished search found
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchResult {
+    /// None when the position has no legal moves
+    pub best_move: Option<BitPackedMove>,
+    /// The expected reply to the best move, when the principal variation has one
+    pub ponder: Option<BitPackedMove>,
+    pub score: i32,
+    pub pv: Vec<BitPackedMove>,
+    pub depth: u8,
+    pub nodes: u64,
+}
+
+/// Everything a search needs, shared with the thread of a running search
+struct EngineState {
+    position: Position,
+    evaluator: Evaluator,
+    tt: TranspositionTable,
+}
+
+impl EngineState {
+    fn search(&mut self, limits: SearchOptions, stop_flag: &Arc<AtomicBool>) -> SearchResult {
+        let best_move =
+            self.evaluator
+                .get_best_move(&mut self.position, limits, &mut self.tt, stop_flag);
+        let result = &self.evaluator.result;
+
+        SearchResult {
+            best_move,
+            ponder: result.pv.get(1).copied(),
+            score: result.score,
+            pv: result.pv.clone(),
+            depth: result.depth,
+            nodes: result.nodes as u64,
+        }
+    }
+}
+
+/*
+   Engine
+   ------
+   The engine as a library: a position, the search and its transposition table behind a few
+   calls, for programs that want moves rather than the UCI protocol.
+
+   The state is shared with the thread of a running search, so calls that change it wait for
+   the search to finish. Stop a search through its handle before changing the position.
+*/
+pub struct Engine {
+    state: Arc<Mutex<EngineState>>,
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        let mut evaluator = Evaluator::new();
+        evaluator.set_silent(true);
+
+        Engine {
+            state: Arc::new(Mutex::new(EngineState {
+                position: Position::new(Some(STARTING_FEN)),
+                evaluator,
+                tt: TranspositionTable::new(DEFAULT_HASH_SIZE_MB),
+            })),
+        }
+    }
+
+    /// Sets up the position after the given moves, in UCI notation, from the given FEN. A FEN
+    /// that can't be read is an error that leaves the position as it was. A move that is not
+    /// legal is an error that leaves the position after the moves before it.
+    pub fn set_position(&mut self, fen: &str, moves: &[&str]) -> Result<(), String> {
+        validate_fen(fen)?;
+
+        let mut state = self.state.lock().unwrap();
+        state.position.set_fen(String::from(fen));
+
+        for &move_string in moves {
+            let legal = match parse_move(&mut state.position, move_string) {
+                Some(m) => state.position.make_move(m, false),
+                None => false,
+            };
+            if !legal {
+                return Err(format!("illegal move: {}", move_string));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the current position as a FEN.
+    pub fn get_fen(&self) -> String {
+        self.state.lock().unwrap().position.as_fen()
+    }
+
+    /// Forgets everything learned in earlier searches, for positions from another game. The
+    /// settings are kept.
+    pub fn new_game(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.evaluator.new_game();
+        state.tt.clear();
+    }
+
+    /// Replaces the transposition table with an empty one of the given size.
+    pub fn set_hash_size(&mut self, hash_size_in_mb: usize) {
+        self.state.lock().unwrap().tt = TranspositionTable::new(hash_size_in_mb.max(1));
+    }
+
+    /// Sets the milliseconds reserved on every move for the delay of sending it.
+    pub fn set_move_overhead(&mut self, move_overhead: u32) {
+        self.state
+            .lock()
+            .unwrap()
+            .evaluator
+            .time_manager
+            .move_overhead = move_overhead.min(5000);
+    }
+
+    /// Sets whether the scores of iterations come with win, draw and loss chances.
+    pub fn set_show_wdl(&mut self, show_wdl: bool) {
+        self.state.lock().unwrap().evaluator.show_wdl = show_wdl;
+    }
+
+    /// Searches the current position on another thread. The handle streams what the search
+    /// reports, stops it and waits for its result.
+    pub fn search(&mut self, limits: SearchOptions) -> SearchHandle {
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+
+        let state = Arc::clone(&self.state);
+        let search_stop_flag = Arc::clone(&stop_flag);
+        let thread = thread::spawn(move || {
+            let mut state = state.lock().unwrap();
+            state
+                .evaluator
+                .set_listener(Box::new(ChannelListener::new(sender)));
+            let result = state.search(limits, &search_stop_flag);
+
+            // Dropping the channel ends the stream of reports
+            state.evaluator.set_silent(true);
+            result
+        });
+
+        SearchHandle {
+            stop_flag,
+            receiver,
+            thread: Some(thread),
+        }
+    }
+
+    /// Searches the current position and returns what was found.
+    pub fn search_blocking(&mut self, limits: SearchOptions) -> SearchResult {
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        self.state.lock().unwrap().search(limits, &stop_flag)
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Engine {
+        Engine::new()
+    }
+}
+
+/// Checks that a FEN has every field set_fen reads, with values it can read, and one king for
+/// each side.
+fn validate_fen(fen: &str) -> Result<(), String> {
+    let invalid = |reason: &str| Err(format!("invalid fen: {}: {}", reason, fen));
+    let sections = fen.split(' ').collect::<Vec<&str>>();
+    if sections.len() < 6 {
+        return invalid("expected six fields");
+    }
+
+    let ranks = sections[0].split('/').collect::<Vec<&str>>();
+    if ranks.len() != 8 {
+        return invalid("expected eight ranks");
+    }
+    for rank in ranks {
+        let mut files = 0;
+        for c in rank.chars() {
+            match c {
+                '1'..='8' => files += c.to_digit(10).unwrap(),
+                'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => files += 1,
+                _ => return invalid("unknown piece"),
+            }
+        }
+        if files != 8 {
+            return invalid("expected eight squares on every rank");
+        }
+    }
+    if sections[0].matches('K').count() != 1 || sections[0].matches('k').count() != 1 {
+        return invalid("expected one king for each side");
+    }
+
+    if sections[1] != "w" && sections[1] != "b" {
+        return invalid("unknown side to move");
+    }
+    if sections[2] != "-"
+        && (sections[2].is_empty() || !sections[2].chars().all(|c| "KQkq".contains(c)))
+    {
+        return invalid("unknown castling rights");
+    }
+
+    let enpassant = sections[3].as_bytes();
+    let is_enpassant_square = enpassant.len() == 2
+        && (b'a'..=b'h').contains(&enpassant[0])
+        && (enpassant[1] == b'3' || enpassant[1] == b'6');
+    if sections[3] != "-" && !is_enpassant_square {
+        return invalid("unknown en passant square");
+    }
+
+    if sections[4].parse::<u32>().is_err() || sections[5].parse::<u32>().is_err() {
+        return invalid("expected move counters");
+    }
+    Ok(())
+}
+
+/// A search running on another thread. Dropping the handle stops the search, without waiting
+/// for it to finish.
+pub struct SearchHandle {
+    stop_flag: Arc<AtomicBool>,
+    receiver: Receiver<SearchInfo>,
+    /// Taken by join
+    thread: Option<JoinHandle<SearchResult>>,
+}
+
+impl SearchHandle {
+    /// Asks the search to stop, it still finishes with the best move found so far.
+    pub fn stop(&self) {
+        self.stop_flag.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().unwrap().is_finished()
+    }
+
+    /// Returns the next report of the search if there is one already, without waiting.
+    pub fn try_next_info(&self) -> Option<SearchInfo> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Returns the reports of the search as they come, ending when the search finishes.
+    pub fn infos(&self) -> mpsc::Iter<'_, SearchInfo> {
+        self.receiver.iter()
+    }
+
+    /// Waits for the search to finish and returns what it found.
+    pub fn join(mut self) -> SearchResult {
+        self.thread.take().unwrap().join().unwrap()
+    }
+}
+
+impl Drop for SearchHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::Engine;
+    use crate::{
+        chess::constants::STARTING_FEN, search::listener::SearchInfo,
+        search::options::SearchOptions,
+    };
+
+    #[test]
+    fn search_blocking_finds_the_mate() {
+        let mut engine = Engine::new();
+        engine
+            .set_position("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", &[])
+            .unwrap();
+
+        let mut limits = SearchOptions::new();
+        limits.depth = Some(5);
+        let result = engine.search_blocking(limits);
+
+        assert_eq!(result.best_move.unwrap().to_string(), "d1d8");
+        assert_eq!(result.pv.first(), result.best_move.as_ref());
+        assert_eq!(result.ponder, None);
+        assert_eq!(result.depth, 5);
+        assert!(result.nodes > 0);
+
+        assert!(engine
+            .set_position(STARTING_FEN, &["e2e4", "e7e5", "e1e3"])
+            .is_err());
+        // The moves before the illegal one are kept
+        assert!(engine
+            .get_fen()
+            .starts_with("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w"));
+
+        // A FEN that can't be read leaves the position as it was
+        for fen in [
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ1BNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e4 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1",
+        ] {
+            assert!(engine.set_position(fen, &[]).is_err(), "{}", fen);
+        }
+        assert!(engine
+            .get_fen()
+            .starts_with("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w"));
+    }
+
+    #[test]
+    fn dropping_the_handle_stops_the_search() {
+        let mut engine = Engine::default();
+        let mut limits = SearchOptions::new();
+        limits.infinite = true;
+        drop(engine.search(limits));
+
+        // Waits for the search to let go of the position
+        assert_eq!(engine.get_fen(), STARTING_FEN);
+    }
+
+    #[test]
+    fn new_game_keeps_the_settings() {
+        let mut engine = Engine::new();
+        engine.set_move_overhead(100);
+        engine.set_show_wdl(true);
+
+        let mut limits = SearchOptions::new();
+        limits.depth = Some(4);
+        engine.search_blocking(limits);
+        engine.new_game();
+
+        let state = engine.state.lock().unwrap();
+        assert_eq!(state.evaluator.time_manager.move_overhead, 100);
+        assert!(state.evaluator.show_wdl);
+        assert_eq!(state.tt.get_hashfull(), 0);
+    }
+
+    #[test]
+    fn search_streams_reports_until_stopped() {
+        let mut engine = Engine::new();
+        engine
+            .set_position(STARTING_FEN, &["e2e4", "c7c5"])
+            .unwrap();
+
+        let mut limits = SearchOptions::new();
+        limits.infinite = true;
+        let handle = engine.search(limits);
+
+        thread::sleep(Duration::from_millis(100));
+        handle.stop();
+        let reports: Vec<SearchInfo> = handle.infos().collect();
+        assert!(reports
+            .iter()
+            .any(|info| matches!(info, SearchInfo::Iteration(_))));
+
+        let result = handle.join();
+        assert!(result.best_move.is_some());
+        assert_eq!(
+            reports.last(),
+            Some(&SearchInfo::BestMove(result.best_move))
+        );
+        assert_eq!(result.ponder, result.pv.get(1).copied());
+    }
+}
@@ -1,11 +1,14 @@
 pub mod board;
 pub mod chess;
+pub mod engine;
 pub mod movegen;
 pub mod pst;
 pub mod search;
 pub mod tt;
 pub mod utils;
 
+pub use engine::{Engine, SearchHandle, SearchResult};
+
 #[derive(Debug, Copy, Clone)]
 pub struct Cutoffs {
     pub total: u32,
@@ -13,6 +13,7 @@ use crate::{
         castling_rights::CastlingRights,
         color::Color,
         constants::{RANK_2, RANK_7},
+        move_list::MoveList,
         piece::Piece,
         square::Square,
     },
@@ -20,54 +21,40 @@ use crate::{
 };
 
 pub trait MoveGenerator {
-    fn generate_legal_moves(&mut self) -> Vec<chess::_move::BitPackedMove>;
-    fn generate_moves(&mut self, only_captures: bool) -> Vec<chess::_move::BitPackedMove>;
-
-    fn generate_knight_moves(
-        &self,
-        move_list: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    );
-    fn generate_bishop_moves(
-        &self,
-        move_list: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    );
-    fn generate_rook_moves(
-        &self,
-        move_list: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    );
-    fn generate_queen_moves(
-        &self,
-        move_list: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    );
-    fn generate_king_moves(
-        &mut self,
-        move_list: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    );
-    fn generate_castle_moves(&self, move_list: &mut Vec<chess::_move::BitPackedMove>);
-    fn generate_pawn_moves(
-        &self,
-        move_list: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    );
-    fn generate_white_pawn_moves(
-        &self,
-        move_list: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    );
-    fn generate_black_pawn_moves(
-        &self,
-        move_list: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    );
+    fn generate_legal_moves(&mut self) -> MoveList;
+    fn generate_moves(&mut self, only_captures: bool) -> MoveList;
+    fn is_pseudo_legal(&mut self, m: chess::_move::BitPackedMove) -> bool;
+    fn get_legality_masks(&self) -> LegalityMasks;
+    fn is_legal(&self, m: chess::_move::BitPackedMove, masks: &LegalityMasks) -> bool;
+
+    fn generate_knight_moves(&self, move_list: &mut MoveList, only_captures: bool);
+    fn generate_bishop_moves(&self, move_list: &mut MoveList, only_captures: bool);
+    fn generate_rook_moves(&self, move_list: &mut MoveList, only_captures: bool);
+    fn generate_queen_moves(&self, move_list: &mut MoveList, only_captures: bool);
+    fn generate_king_moves(&mut self, move_list: &mut MoveList, only_captures: bool);
+    fn generate_castle_moves(&self, move_list: &mut MoveList);
+    fn generate_pawn_moves(&self, move_list: &mut MoveList, only_captures: bool);
+    fn generate_white_pawn_moves(&self, move_list: &mut MoveList, only_captures: bool);
+    fn generate_black_pawn_moves(&self, move_list: &mut MoveList, only_captures: bool);
 
     fn perft(&mut self, depth: u8) -> u64;
 }
 
+/// The checkers and pins of the side to move, computed once per position so that pseudo-legal
+/// moves can be checked for legality without making them.
+#[derive(Clone, Copy)]
+pub struct LegalityMasks {
+    pub king_square: Square,
+    /// Enemy pieces giving check
+    pub checkers: u64,
+    /// Squares a non-king move must land on to resolve a check, all squares when not in check
+    pub check_mask: u64,
+    /// Friendly pieces pinned to the king
+    pub pinned: u64,
+    /// For each pinned piece, the squares between the king and the pinner plus the pinner
+    pub pin_lines: [u64; 64],
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct PerftResult {
     pub depth: u8,
@@ -122,11 +109,7 @@ impl std::ops::Add for PerftResult {
 }
 
 impl MoveGenerator for Position {
-    fn generate_black_pawn_moves(
-        &self,
-        moves: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    ) {
+    fn generate_black_pawn_moves(&self, moves: &mut MoveList, only_captures: bool) {
         let mut piece_bitboard =
             self.bitboards[Piece::BlackPawn as usize] & !chess::constants::RANK_1;
 
@@ -231,11 +214,7 @@ impl MoveGenerator for Position {
         }
     }
 
-    fn generate_white_pawn_moves(
-        &self,
-        moves: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    ) {
+    fn generate_white_pawn_moves(&self, moves: &mut MoveList, only_captures: bool) {
         let mut piece_bitboard =
             self.bitboards[Piece::WhitePawn as usize] & !chess::constants::RANK_8;
 
@@ -340,11 +319,7 @@ impl MoveGenerator for Position {
         }
     }
 
-    fn generate_pawn_moves(
-        &self,
-        move_list: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    ) {
+    fn generate_pawn_moves(&self, move_list: &mut MoveList, only_captures: bool) {
         // white pawn moves
         if self.turn == Color::White {
             self.generate_white_pawn_moves(move_list, only_captures)
@@ -355,11 +330,7 @@ impl MoveGenerator for Position {
         }
     }
 
-    fn generate_knight_moves(
-        &self,
-        moves: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    ) {
+    fn generate_knight_moves(&self, moves: &mut MoveList, only_captures: bool) {
         let piece = if self.turn == Color::White {
             Piece::WhiteKnight
         } else {
@@ -391,11 +362,7 @@ impl MoveGenerator for Position {
         }
     }
 
-    fn generate_bishop_moves(
-        &self,
-        moves: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    ) {
+    fn generate_bishop_moves(&self, moves: &mut MoveList, only_captures: bool) {
         let piece = if self.turn == Color::White {
             Piece::WhiteBishop
         } else {
@@ -430,11 +397,7 @@ impl MoveGenerator for Position {
         }
     }
 
-    fn generate_rook_moves(
-        &self,
-        moves: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    ) {
+    fn generate_rook_moves(&self, moves: &mut MoveList, only_captures: bool) {
         let piece = if self.turn == Color::White {
             Piece::WhiteRook
         } else {
@@ -470,11 +433,7 @@ impl MoveGenerator for Position {
         }
     }
 
-    fn generate_queen_moves(
-        &self,
-        moves: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    ) {
+    fn generate_queen_moves(&self, moves: &mut MoveList, only_captures: bool) {
         let piece = if self.turn == Color::White {
             Piece::WhiteQueen
         } else {
@@ -509,11 +468,7 @@ impl MoveGenerator for Position {
         }
     }
 
-    fn generate_king_moves(
-        &mut self,
-        moves: &mut Vec<chess::_move::BitPackedMove>,
-        only_captures: bool,
-    ) {
+    fn generate_king_moves(&mut self, moves: &mut MoveList, only_captures: bool) {
         let piece = if self.turn == Color::White {
             Piece::WhiteKing
         } else {
@@ -563,7 +518,7 @@ impl MoveGenerator for Position {
         }
     }
 
-    fn generate_castle_moves(&self, moves: &mut Vec<chess::_move::BitPackedMove>) {
+    fn generate_castle_moves(&self, moves: &mut MoveList) {
         if self.is_in_check() {
             return;
         }
@@ -631,8 +586,8 @@ impl MoveGenerator for Position {
         }
     }
 
-    fn generate_moves(&mut self, only_captures: bool) -> Vec<chess::_move::BitPackedMove> {
-        let mut moves = Vec::with_capacity(256);
+    fn generate_moves(&mut self, only_captures: bool) -> MoveList {
+        let mut moves = MoveList::new();
 
         self.generate_pawn_moves(&mut moves, only_captures);
         self.generate_knight_moves(&mut moves, only_captures);
@@ -644,14 +599,205 @@ impl MoveGenerator for Position {
         return moves;
     }
 
-    fn generate_legal_moves(&mut self) -> Vec<chess::_move::BitPackedMove> {
-        let mut moves = Vec::with_capacity(256);
+    /// Returns true if the move could be generated in the current position, ignoring whether
+    /// it leaves the king in check. Used to validate moves taken from the transposition table
+    /// and killer tables before they are searched without generating the whole move list.
+    fn is_pseudo_legal(&mut self, m: chess::_move::BitPackedMove) -> bool {
+        let piece = m.get_piece();
+        if piece == Piece::Empty || (piece as usize / 6) != self.turn as usize {
+            return false;
+        }
+
+        let from = m.get_from();
+        let to = m.get_to();
+        if from == Square::NoSq || to == Square::NoSq || self.mailbox[from as usize] != piece {
+            return false;
+        }
+
+        // These are rare enough that checking against the generated moves is fine
+        if m.is_castle() || m.is_enpassant() {
+            return self.generate_moves(false).contains(&m);
+        }
+
+        let target = self.mailbox[to as usize];
+        if m.get_capture() != target
+            || (target != Piece::Empty && (target as usize / 6) == self.turn as usize)
+            || target == Piece::WhiteKing
+            || target == Piece::BlackKing
+        {
+            return false;
+        }
+
+        if piece == Piece::WhitePawn || piece == Piece::BlackPawn {
+            let is_last_rank = if self.turn == Color::White {
+                to <= Square::H8
+            } else {
+                to >= Square::A1
+            };
+            let promotion = m.get_promotion();
+            if is_last_rank != m.is_promotion()
+                || (m.is_promotion()
+                    && (promotion as usize / 6 != self.turn as usize
+                        || promotion == Piece::WhitePawn
+                        || promotion == Piece::BlackPawn
+                        || promotion == Piece::WhiteKing
+                        || promotion == Piece::BlackKing))
+            {
+                return false;
+            }
+
+            if m.is_capture() {
+                return get_bit(
+                    self.pawn_attacks[self.turn as usize][from as usize],
+                    to as u8,
+                ) != 0;
+            }
+
+            let (single_push, double_push, start_rank) = if self.turn == Color::White {
+                (from as i32 - 8, from as i32 - 16, *RANK_2)
+            } else {
+                (from as i32 + 8, from as i32 + 16, *RANK_7)
+            };
+
+            return to as i32 == single_push
+                || (to as i32 == double_push
+                    && get_bit(start_rank, from as u8) != 0
+                    && get_bit(self.occupancies[2], single_push as u8) == 0);
+        }
+
+        if m.is_promotion() {
+            return false;
+        }
+
+        let attacks = self.get_piece_attacks(piece, from, self.occupancies[2]);
+        if get_bit(attacks, to as u8) == 0 {
+            return false;
+        }
+
+        // King moves aren't verified by make_move, so make sure the target square is safe
+        if piece == Piece::WhiteKing || piece == Piece::BlackKing {
+            return !self.is_square_attacked_w_occupancy(
+                to,
+                !self.turn,
+                self.occupancies[2] & !(1u64 << from as u8),
+            );
+        }
+
+        true
+    }
+
+    fn get_legality_masks(&self) -> LegalityMasks {
+        let king_bitboard = self.bitboards[(self.turn as usize * 6) + (Piece::WhiteKing as usize)];
+
+        // Without a king (its capture was searched from an illegal position) nothing is legal
+        if king_bitboard == 0 {
+            return LegalityMasks {
+                king_square: Square::NoSq,
+                checkers: 0,
+                check_mask: 0,
+                pinned: 0,
+                pin_lines: [0; 64],
+            };
+        }
+
+        let king_square = Square::from(utils::get_lsb(king_bitboard));
+        let friendly_pieces = self.occupancies[self.turn as usize];
+        let enemy_pieces = self.occupancies[!self.turn as usize];
+        let enemy_offset = !self.turn as usize * 6;
+
+        let checkers = self.get_attackers_to(king_square, self.occupancies[2]) & enemy_pieces;
+        let check_mask = match checkers.count_ones() {
+            0 => !0,
+            1 => {
+                checkers
+                    | self.get_squares_between(king_square, Square::from(utils::get_lsb(checkers)))
+            }
+            // Only the king can move out of a double check
+            _ => 0,
+        };
+
+        // Enemy sliders that would attack the king if the friendly pieces were not in the way
+        let enemy_queens = self.bitboards[Piece::WhiteQueen as usize + enemy_offset];
+        let mut snipers = (self.get_rook_magic_attacks(king_square, enemy_pieces)
+            & (self.bitboards[Piece::WhiteRook as usize + enemy_offset] | enemy_queens))
+            | (self.get_bishop_magic_attacks(king_square, enemy_pieces)
+                & (self.bitboards[Piece::WhiteBishop as usize + enemy_offset] | enemy_queens));
+
+        let mut pinned = 0;
+        let mut pin_lines = [0u64; 64];
+        while snipers != 0 {
+            let sniper = utils::pop_lsb(&mut snipers);
+            let between = self.get_squares_between(king_square, Square::from(sniper));
+            let blockers = between & self.occupancies[2];
+
+            if blockers.count_ones() == 1 && blockers & friendly_pieces != 0 {
+                pinned |= blockers;
+                pin_lines[utils::get_lsb(blockers) as usize] = between | (1u64 << sniper);
+            }
+        }
+
+        LegalityMasks {
+            king_square,
+            checkers,
+            check_mask,
+            pinned,
+            pin_lines,
+        }
+    }
+
+    /// Returns true if a pseudo-legal move does not leave the king in check.
+    fn is_legal(&self, m: chess::_move::BitPackedMove, masks: &LegalityMasks) -> bool {
+        let from = m.get_from() as u8;
+        let to = m.get_to() as u8;
+
+        if masks.king_square == Square::NoSq {
+            return false;
+        }
+
+        // King moves and castles are only generated onto safe squares
+        if m.get_piece() == Piece::WhiteKing || m.get_piece() == Piece::BlackKing {
+            return true;
+        }
+
+        if m.is_enpassant() {
+            // Removing both pawns from the rank can expose the king to a slider, so look at
+            // the board as it will be after the capture
+            let captured_square = if self.turn == Color::White {
+                to + 8
+            } else {
+                to - 8
+            };
+            let occupancy =
+                (self.occupancies[2] & !(1u64 << from) & !(1u64 << captured_square)) | (1u64 << to);
+            let enemy_offset = !self.turn as usize * 6;
+            let enemy_queens = self.bitboards[Piece::WhiteQueen as usize + enemy_offset];
+
+            let slider_attacks = (self.get_rook_magic_attacks(masks.king_square, occupancy)
+                & (self.bitboards[Piece::WhiteRook as usize + enemy_offset] | enemy_queens))
+                | (self.get_bishop_magic_attacks(masks.king_square, occupancy)
+                    & (self.bitboards[Piece::WhiteBishop as usize + enemy_offset] | enemy_queens));
+            let other_checkers = masks.checkers
+                & !(1u64 << captured_square)
+                & (self.bitboards[Piece::WhiteKnight as usize + enemy_offset]
+                    | self.bitboards[Piece::WhitePawn as usize + enemy_offset]);
+
+            return slider_attacks == 0 && other_checkers == 0;
+        }
+
+        if get_bit(masks.check_mask, to) == 0 {
+            return false;
+        }
+
+        get_bit(masks.pinned, from) == 0 || get_bit(masks.pin_lines[from as usize], to) != 0
+    }
+
+    fn generate_legal_moves(&mut self) -> MoveList {
+        let mut moves = MoveList::new();
+        let masks = self.get_legality_masks();
 
         for m in self.generate_moves(false) {
-            let is_legal_move = self.make_move(m, false);
-            if is_legal_move {
+            if self.is_legal(m, &masks) {
                 moves.push(m);
-                self.unmake_move();
             }
         }
 
@@ -663,27 +809,18 @@ impl MoveGenerator for Position {
             return 1;
         }
 
-        let moves = self.generate_moves(false);
+        let moves = self.generate_legal_moves();
 
         // Bulk counting: at depth 1, just count legal moves without recursing
         if depth == 1 {
-            let mut count = 0;
-            let state = self.save_state();
-            for m in moves {
-                if self.make_move_unchecked(m) {
-                    count += 1;
-                }
-                self.restore_state(state);
-            }
-            return count;
+            return moves.len() as u64;
         }
 
         let mut nodes = 0;
         let state = self.save_state();
         for m in moves {
-            if self.make_move_unchecked(m) {
-                nodes += self.perft(depth - 1);
-            }
+            self.make_move_unchecked(m);
+            nodes += self.perft(depth - 1);
             self.restore_state(state);
         }
 
@@ -706,4 +843,40 @@ mod tests {
         assert!(position.perft(3) == 8902);
         assert!(position.perft(4) == 197281);
     }
+
+    #[test]
+    fn legal_moves_match_known_perft_counts() {
+        let positions: [(&str, &[u64]); 4] = [
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                &[48, 2039, 97862],
+            ),
+            // En passant captures that would expose the king along the rank
+            (
+                "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                &[14, 191, 2812, 43238, 674624],
+            ),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                &[6, 264, 9467, 422333],
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                &[44, 1486, 62379],
+            ),
+        ];
+
+        for (fen, counts) in positions {
+            let mut position = board::Position::new(Some(fen));
+            for (depth, &count) in counts.iter().enumerate() {
+                assert_eq!(
+                    position.perft(depth as u8 + 1),
+                    count,
+                    "{} depth {}",
+                    fen,
+                    depth + 1
+                );
+            }
+        }
+    }
 }
@@ -1,4 +1,11 @@
 pub mod constants;
+pub mod correction_history;
 pub mod evaluate;
+pub mod history;
+pub mod listener;
 pub mod utils;
 pub mod options;
+pub mod pawn_table;
+pub mod search_stack;
+pub mod move_picker;
+pub mod time_manager;
@@ -18,16 +18,207 @@ pub static _MVV_LVA: [[u32; 12]; 12] = [
     [100, 200, 300, 400, 500, 600, 100, 200, 300, 400, 500, 600],
 ];
 
-pub const MAX_PLY: usize = 64;
+/// The deepest the search can go from the root, extensions and quiescence included. Set it at
+/// build time with the REDTAIL_MAX_PLY environment variable, e.g. `REDTAIL_MAX_PLY=256 cargo
+/// build`. Raising it costs memory for every table indexed by ply.
+pub const MAX_PLY: usize = parse_max_ply(option_env!("REDTAIL_MAX_PLY"), 128);
+/// Plies past the deepest iteration left for extensions and quiescence
+const MAX_PLY_HEADROOM: usize = 28;
+/// Iterative deepening stops at this depth
+pub const MAX_DEPTH: u8 = (MAX_PLY - MAX_PLY_HEADROOM) as u8;
 
-pub const _REDUCTION_LIMIT: u8 = 3;
-pub const _FULL_DEPTH_MOVES: u8 = 3;
+// Extensions raise depths past the iteration depth, and depths are u8
+const _: () = assert!(
+    MAX_PLY >= 2 * MAX_PLY_HEADROOM,
+    "REDTAIL_MAX_PLY is too small to leave room for extensions and quiescence"
+);
+const _: () = assert!(
+    MAX_PLY - MAX_PLY_HEADROOM <= u8::MAX as usize - 8,
+    "REDTAIL_MAX_PLY is too large for depths to fit in a u8"
+);
+
+const fn parse_max_ply(value: Option<&str>, default: usize) -> usize {
+    let digits = match value {
+        Some(value) => value.as_bytes(),
+        None => return default,
+    };
+    assert!(!digits.is_empty(), "REDTAIL_MAX_PLY must be a number");
+
+    let mut max_ply = 0;
+    let mut i = 0;
+    while i < digits.len() {
+        assert!(
+            digits[i].is_ascii_digit(),
+            "REDTAIL_MAX_PLY must be a number"
+        );
+        max_ply = max_ply * 10 + (digits[i] - b'0') as usize;
+        i += 1;
+    }
+    max_ply
+}
+
+/// Bounds every score, the window of a search without an estimate of its result
+pub const INFINITY: i32 = 32_000;
+/// The score of checkmating at the root, mates further away score closer to zero
+pub const MATE: i32 = 31_000;
+/// Scores beyond this are mate scores, whose distance to mate depends on the ply
+pub const MATE_IN_MAX_PLY: i32 = MATE - MAX_PLY as i32;
+/// Stored in place of the static evaluation of positions that are in check, below any real score
+pub const NO_STATIC_EVAL: i32 = -INFINITY - 1;
+
+// The transposition table stores scores and static evaluations in 16 bits
+const _: () = assert!(INFINITY <= i16::MAX as i32 && NO_STATIC_EVAL >= i16::MIN as i32);
+
+/// History scores stay between -MAX_HISTORY and MAX_HISTORY
+pub const MAX_HISTORY: i32 = 16384;
+/// A cutoff at depth d earns a bonus of HISTORY_BONUS_SCALE * d * d, up to MAX_HISTORY_BONUS
+pub const HISTORY_BONUS_SCALE: i32 = 32;
+pub const MAX_HISTORY_BONUS: i32 = 1536;
+
+/// Iterations from this depth search a window of ASPIRATION_DELTA around the previous score,
+/// doubling the margin on every fail and searching the full window after enough of them
+pub const ASPIRATION_MIN_DEPTH: u8 = 4;
+pub const ASPIRATION_DELTA: i32 = 25;
+pub const ASPIRATION_MAX_FAILURES: u32 = 4;
+
+/// Static evaluation corrections are kept per side for this many pawn structures
+pub const CORRECTION_HISTORY_SIZE: usize = 16384;
+/// Corrections are stored in units of 1 / CORRECTION_GRAIN centipawns, up to MAX_CORRECTION
+pub const CORRECTION_GRAIN: i32 = 256;
+pub const MAX_CORRECTION: i32 = 64 * CORRECTION_GRAIN;
+/// A node at depth d moves its correction by (d + 1) / 256 of the way, at most this many 256ths
+pub const MAX_CORRECTION_WEIGHT: i32 = 16;
+
+/// Late move reductions apply from this depth, to moves after this many have been searched
+pub const LMR_MIN_DEPTH: u8 = 3;
+pub const LMR_MIN_MOVES: usize = 3;
+/// The base reduction is LMR_BASE + ln(depth) * ln(move number) / LMR_DIVISOR
+pub const LMR_BASE: f64 = 0.75;
+pub const LMR_DIVISOR: f64 = 2.25;
+/// Every this much history score reduces a quiet move by one ply less
+pub const LMR_HISTORY_DIVISOR: i32 = 8192;
 
 pub const DOUBLED_PAWN_PENALTY: i32 = -15;
 pub const ISOLATED_PAWN_PENALTY: i32 = -15;
 pub const PASSED_PAWN_BONUS: [i32; 8] = [0, 5, 10, 20, 35, 60, 100, 200];
 
+pub const DOUBLED_PAWN_PENALTY_EG: i32 = -25;
+pub const ISOLATED_PAWN_PENALTY_EG: i32 = -10;
+pub const PASSED_PAWN_BONUS_EG: [i32; 8] = [0, 10, 20, 35, 60, 100, 160, 250];
+
+pub const BACKWARD_PAWN_PENALTY: i32 = -10;
+pub const BACKWARD_PAWN_PENALTY_EG: i32 = -8;
+
+/// Bonuses indexed by the relative rank of a pawn defended by another pawn
+pub const CONNECTED_PAWN_BONUS: [i32; 8] = [0, 0, 5, 8, 12, 20, 35, 0];
+pub const CONNECTED_PAWN_BONUS_EG: [i32; 8] = [0, 0, 3, 5, 10, 18, 30, 0];
+
+/// Bonuses indexed by the relative rank of a pawn standing next to another pawn
+pub const PHALANX_PAWN_BONUS: [i32; 8] = [0, 0, 3, 6, 10, 18, 30, 0];
+pub const PHALANX_PAWN_BONUS_EG: [i32; 8] = [0, 0, 2, 4, 8, 15, 25, 0];
+
+/// Bonuses indexed by the relative rank of a pawn that can become passed by force
+pub const CANDIDATE_PASSER_BONUS: [i32; 8] = [0, 2, 4, 8, 15, 25, 0, 0];
+pub const CANDIDATE_PASSER_BONUS_EG: [i32; 8] = [0, 5, 10, 15, 25, 40, 0, 0];
+
+/// Endgame bonus for a passed pawn whose stop square is empty, indexed by relative rank
+pub const FREE_PASSER_BONUS_EG: [i32; 8] = [0, 0, 5, 10, 20, 35, 60, 0];
+
+pub const PAWN_HASH_SIZE_MB: usize = 2;
+/// The transposition table size of an engine embedded as a library, until told otherwise
+pub const DEFAULT_HASH_SIZE_MB: usize = 16;
+
 pub const SEMI_OPEN_FILE_SCORE: i32 = 10;
 pub const OPEN_FILE_SCORE: i32 = 20;
 
-pub const FUTILITY_MARGIN: i32 = 200;
+/// Quiet moves are not searched when the static evaluation plus this margin, and another margin
+/// per ply of depth, can't reach alpha
+pub const FUTILITY_DEPTH: u8 = 6;
+pub const FUTILITY_MARGIN: i32 = 100;
+pub const FUTILITY_DEPTH_MARGIN: i32 = 100;
+
+/// Nodes whose static evaluation beats beta by this margin per ply of depth are cut off
+pub const RFP_DEPTH: u8 = 7;
+pub const RFP_MARGIN: i32 = 80;
+
+/// Nodes whose static evaluation is this margin per ply of depth below alpha drop into quiescence
+pub const RAZORING_DEPTH: u8 = 2;
+pub const RAZORING_MARGIN: i32 = 250;
+
+/// Null move pruning reduces by NMP_BASE_REDUCTION + depth / NMP_DEPTH_DIVISOR, plus a ply for
+/// every NMP_EVAL_DIVISOR the static evaluation beats beta by, up to NMP_MAX_EVAL_REDUCTION
+pub const NMP_MIN_DEPTH: u8 = 3;
+pub const NMP_BASE_REDUCTION: u8 = 3;
+pub const NMP_DEPTH_DIVISOR: u8 = 3;
+pub const NMP_EVAL_DIVISOR: i32 = 200;
+pub const NMP_MAX_EVAL_REDUCTION: i32 = 3;
+/// From this depth null move cutoffs are verified by a reduced search without null moves
+pub const NMP_VERIFICATION_DEPTH: u8 = 10;
+
+/// The transposition table move is extended when every other move fails low against its score
+/// minus this margin per ply of depth, in a search of half the depth
+pub const SE_MIN_DEPTH: u8 = 7;
+pub const SE_TT_DEPTH_MARGIN: u8 = 3;
+pub const SE_MARGIN: i32 = 2;
+
+/// PV nodes without a move from the transposition table are searched a ply shallower from this
+/// depth
+pub const IIR_MIN_DEPTH: u8 = 4;
+
+/// Quiet moves after the first 3 + depth * depth are not searched, half as many when not improving
+pub const LMP_DEPTH: u8 = 8;
+
+/// Quiet moves whose history is below minus this margin per ply of depth are not searched
+pub const HISTORY_PRUNING_DEPTH: u8 = 3;
+pub const HISTORY_PRUNING_MARGIN: i32 = 2048;
+
+/// Milliseconds reserved on every move for the delay between sending it and the clock stopping
+pub const DEFAULT_MOVE_OVERHEAD: u32 = 30;
+/// Moves left until the time control is assumed to be at most this many
+pub const DEFAULT_MOVES_TO_GO: u32 = 30;
+pub const MAX_MOVES_TO_GO: u32 = 50;
+/// The maximum time for a move is this many times its optimum time
+pub const MAXIMUM_TIME_FACTOR: u128 = 4;
+/// Roughly how many times longer an iteration takes than the previous one
+pub const ITERATION_TIME_GROWTH: u128 = 2;
+/// Milliseconds between polls of the clock during search
+pub const DEFAULT_POLL_INTERVAL: u64 = 5;
+pub const MIN_NODES_BETWEEN_POLLS: u64 = 256;
+pub const MAX_NODES_BETWEEN_POLLS: u64 = 65536;
+
+/// Captures in quiescence are skipped when winning the captured piece plus this margin can't
+/// reach alpha
+pub const DELTA_MARGIN: i32 = 200;
+
+/// Milliseconds into a search after which the root move being searched is reported
+pub const CURRMOVE_DELAY: u128 = 1000;
+
+/// Win chances follow 1 / (1 + e^((WDL_MIDPOINT - score) / WDL_SCALE)), the chance to lose the
+/// same for the negated score, and the rest are draws
+pub const WDL_MIDPOINT: i32 = 250;
+pub const WDL_SCALE: f64 = 80.0;
+
+pub const SEE_PRUNING_DEPTH: u8 = 3;
+pub const SEE_QUIET_MARGIN: i32 = 60;
+
+pub const KING_SHIELD_BONUS: i32 = 6;
+pub const KING_SEMI_OPEN_FILE_PENALTY: i32 = 15;
+pub const KING_OPEN_FILE_PENALTY: i32 = 10;
+
+/// Penalty for an enemy pawn storming the king, indexed by its rank distance to the king
+pub const PAWN_STORM_PENALTY: [i32; 8] = [0, 30, 20, 10, 5, 0, 0, 0];
+
+/// Attack units contributed per king zone square attacked, indexed by piece type
+pub const KING_ATTACK_WEIGHTS: [i32; 6] = [0, 2, 2, 3, 5, 0];
+
+/// Attack units contributed per safe checking square, indexed by piece type
+pub const SAFE_CHECK_WEIGHTS: [i32; 6] = [0, 3, 2, 4, 6, 0];
+
+/// Non-linear king danger, indexed by the accumulated attack units
+pub const KING_DANGER_TABLE: [i32; 100] = [
+    0, 0, 1, 2, 3, 5, 7, 9, 12, 15, 18, 22, 26, 30, 35, 39, 44, 50, 56, 62, 68, 75, 82, 85, 89, 97,
+    105, 113, 122, 131, 140, 150, 169, 180, 191, 202, 213, 225, 237, 248, 260, 272, 283, 295, 307,
+    319, 330, 342, 354, 366, 377, 389, 401, 412, 424, 436, 448, 459, 471, 483, 494, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+];
@@ -0,0 +1,100 @@
+use crate::{
+    chess::color::Color,
+    search::constants::{
+        CORRECTION_GRAIN, CORRECTION_HISTORY_SIZE, MATE_IN_MAX_PLY, MAX_CORRECTION,
+        MAX_CORRECTION_WEIGHT,
+    },
+};
+
+/*
+   CorrectionHistory
+   -----------------
+   Learns how far the static evaluation is off in positions with a given pawn structure, by side
+   to move, from the difference between the static evaluation and the search result of earlier
+   nodes. The evaluation misjudges some structures consistently, and correcting it makes the
+   pruning decisions that rely on it more accurate.
+
+   Entries are kept in units of 1 / CORRECTION_GRAIN centipawns, so small differences still add
+   up. Each update moves an entry towards the latest difference by a weight that grows with the
+   depth of the search behind it.
+*/
+pub struct CorrectionHistory {
+    table: Vec<i32>,
+}
+
+impl CorrectionHistory {
+    pub fn new() -> CorrectionHistory {
+        CorrectionHistory {
+            table: vec![0; 2 * CORRECTION_HISTORY_SIZE],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.table.fill(0);
+    }
+
+    /// Returns the static evaluation adjusted by what was learned about the pawn structure.
+    pub fn correct(&self, side: Color, pawn_hash: u64, static_eval: i32) -> i32 {
+        let correction = self.table[CorrectionHistory::index(side, pawn_hash)] / CORRECTION_GRAIN;
+        (static_eval + correction).clamp(-MATE_IN_MAX_PLY + 1, MATE_IN_MAX_PLY - 1)
+    }
+
+    /// Moves the entry for the pawn structure towards the difference between the search result
+    /// of a node at the given depth and its uncorrected static evaluation.
+    pub fn update(&mut self, side: Color, pawn_hash: u64, depth: u8, static_eval: i32, score: i32) {
+        let entry = &mut self.table[CorrectionHistory::index(side, pawn_hash)];
+        let difference = (score - static_eval) * CORRECTION_GRAIN;
+        let weight = (depth as i32 + 1).min(MAX_CORRECTION_WEIGHT);
+
+        *entry = ((*entry * (256 - weight) + difference * weight) / 256)
+            .clamp(-MAX_CORRECTION, MAX_CORRECTION);
+    }
+
+    fn index(side: Color, pawn_hash: u64) -> usize {
+        side as usize * CORRECTION_HISTORY_SIZE + (pawn_hash as usize % CORRECTION_HISTORY_SIZE)
+    }
+}
+
+impl Default for CorrectionHistory {
+    fn default() -> CorrectionHistory {
+        CorrectionHistory::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CorrectionHistory;
+    use crate::{
+        chess::color::Color,
+        search::constants::{CORRECTION_GRAIN, MAX_CORRECTION},
+    };
+
+    #[test]
+    fn learns_the_evaluation_error_of_a_pawn_structure() {
+        let mut correction_history = CorrectionHistory::new();
+        let pawn_hash = 0x1234_5678_9abc_def0;
+
+        // Searches keep finding the position better than it looks
+        for _ in 0..100 {
+            correction_history.update(Color::White, pawn_hash, 10, 0, 60);
+        }
+        let corrected = correction_history.correct(Color::White, pawn_hash, 0);
+        assert!(corrected > 50 && corrected <= 60);
+
+        // Other structures and the other side are left alone
+        assert_eq!(correction_history.correct(Color::Black, pawn_hash, 0), 0);
+        assert_eq!(
+            correction_history.correct(Color::White, pawn_hash + 1, 0),
+            0
+        );
+
+        // Large errors are only partly corrected
+        for _ in 0..100 {
+            correction_history.update(Color::White, pawn_hash, 10, 0, 5000);
+        }
+        assert_eq!(
+            correction_history.correct(Color::White, pawn_hash, 0),
+            MAX_CORRECTION / CORRECTION_GRAIN
+        );
+    }
+}
@@ -1,22 +1,35 @@
 use std::{
-    collections::BinaryHeap,
+    cmp::Reverse,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
+    time::Instant,
 };
 
 use crate::{
-    board::{Board, Position},
+    board::{
+        constants::SEE_PIECE_VALUES, Board, Position, ENDGAME_PHASE_SCORE, OPENING_GAME_PHASE_SCORE,
+    },
     chess::{
         self,
-        _move::{BitPackedMove, PrioritizedMove},
+        _move::BitPackedMove,
         color::Color,
+        move_list::{MoveList, MAX_MOVES},
         piece::Piece,
+        square::Square,
     },
-    movegen::MoveGenerator,
     search::constants::*,
+    search::correction_history::CorrectionHistory,
+    search::history::History,
+    search::listener::{
+        Bound, IterationInfo, SearchInfo, SearchListener, SilentListener, UciListener,
+    },
+    search::move_picker::MovePicker,
     search::options::*,
+    search::pawn_table::{PawnHashEntry, PawnHashTable},
+    search::search_stack::SearchStack,
+    search::time_manager::TimeManager,
     tt::{self, TranspositionTable},
     utils, Cutoffs,
 };
@@ -27,8 +40,30 @@ pub struct PositionEvaluation {
     pub best_move: Option<chess::_move::BitPackedMove>,
     pub depth: u8,
     pub ply: u32,
+    /// The deepest ply reached in the current iteration, quiescence included
+    pub seldepth: u32,
     pub nodes: i32,
     pub cutoffs: Cutoffs,
+    /// The principal variation of the last completed iteration
+    pub pv: Vec<BitPackedMove>,
+}
+
+/// The kind of node being searched, which decides how much of its search may be cut short
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NodeType {
+    /// The position the search started from, whose moves are kept in the root move list
+    Root,
+    /// Nodes on the principal variation, searched with an open window for an exact score
+    PV,
+    /// Nodes searched with a null window, only to prove a bound
+    NonPV,
+}
+
+/// A legal move of the root position, with the nodes spent searching it over all iterations
+#[derive(Clone, Copy, Debug)]
+pub struct RootMove {
+    pub m: BitPackedMove,
+    pub nodes: u64,
 }
 
 /// Triangular PV table for storing principal variation during search
@@ -84,15 +119,31 @@ impl PVTable {
 pub struct Evaluator {
     pub running: bool,
     pub result: PositionEvaluation,
-    pub killer_moves: [[chess::_move::BitPackedMove; MAX_PLY]; 2],
-    pub history_moves: [[u32; MAX_PLY]; 12],
-    pub started_at: u128,
+    pub history: History,
+    pub correction_history: CorrectionHistory,
+    /// What the search keeps about each node of the current search path, by ply
+    stack: SearchStack,
+    /// Null moves are not tried before this ply, while a null move cutoff is being verified
+    null_move_min_ply: usize,
+    /// Late move reductions, by depth and move number
+    reductions: [[u8; 64]; MAX_PLY],
+    pub started_at: Instant,
     pub options: SearchOptions,
     pub repetition_table: Vec<u64>,
     counter_move_table: [[BitPackedMove; 64]; 64],
     stop_flag: Option<Arc<AtomicBool>>,
     pv_table: PVTable,
-    silent: bool,
+    /// The moves of the root position, the best move first and the rest by the effort spent on
+    /// them, which is the order the next iteration searches them in
+    root_moves: Vec<RootMove>,
+    pawn_table: PawnHashTable,
+    pub time_manager: TimeManager,
+    /// The node count at which the clock is next checked
+    next_time_check: i32,
+    /// Where reports of the search go
+    listener: Box<dyn SearchListener>,
+    /// Whether to report the expected win, draw and loss chances with every score
+    pub show_wdl: bool,
 }
 
 impl Evaluator {
@@ -104,23 +155,67 @@ impl Evaluator {
                 best_move: None,
                 depth: 0,
                 ply: 0,
+                seldepth: 0,
                 nodes: 0,
                 cutoffs: Cutoffs::new(),
+                pv: Vec::new(),
             },
-            killer_moves: [[chess::_move::BitPackedMove::default(); MAX_PLY]; 2],
-            history_moves: [[0; MAX_PLY]; 12],
-            started_at: 0,
+            history: History::new(),
+            correction_history: CorrectionHistory::new(),
+            stack: SearchStack::new(),
+            null_move_min_ply: 0,
+            reductions: Evaluator::init_reductions(),
+            started_at: Instant::now(),
             options: SearchOptions::new(),
             repetition_table: Vec::with_capacity(150),
             counter_move_table: [[BitPackedMove::default(); 64]; 64],
             stop_flag: None,
             pv_table: PVTable::new(),
-            silent: false,
+            root_moves: Vec::with_capacity(MAX_MOVES),
+            pawn_table: PawnHashTable::new(PAWN_HASH_SIZE_MB),
+            time_manager: TimeManager::new(),
+            next_time_check: 0,
+            listener: Box::new(UciListener),
+            show_wdl: false,
         }
     }
 
+    fn init_reductions() -> [[u8; 64]; MAX_PLY] {
+        let mut reductions = [[0; 64]; MAX_PLY];
+        for (depth, row) in reductions.iter_mut().enumerate().skip(1) {
+            for (move_number, reduction) in row.iter_mut().enumerate().skip(1) {
+                let r = LMR_BASE + (depth as f64).ln() * (move_number as f64).ln() / LMR_DIVISOR;
+                *reduction = r.max(0.0) as u8;
+            }
+        }
+        reductions
+    }
+
+    /// Returns the base late move reduction for the given move number at the given depth.
+    pub fn get_reduction(&self, depth: u8, move_number: usize) -> u8 {
+        self.reductions[(depth as usize).min(MAX_PLY - 1)][move_number.min(63)]
+    }
+
+    /// Sends reports of the search to the given listener instead of stdout.
+    pub fn set_listener(&mut self, listener: Box<dyn SearchListener>) {
+        self.listener = listener;
+    }
+
+    /// Forgets what earlier searches learned about moves and pawn structures, keeping the
+    /// settings.
+    pub fn new_game(&mut self) {
+        self.history.clear();
+        self.correction_history.clear();
+        self.counter_move_table = [[BitPackedMove::default(); 64]; 64];
+        self.pawn_table.clear();
+    }
+
     pub fn set_silent(&mut self, silent: bool) {
-        self.silent = silent;
+        if silent {
+            self.set_listener(Box::new(SilentListener));
+        } else {
+            self.set_listener(Box::new(UciListener));
+        }
     }
 
     fn is_stopped(&self) -> bool {
@@ -131,48 +226,6 @@ impl Evaluator {
         }
     }
 
-    /// Determines the time to spend on a move based on the time left for the side to move
-    /// as well as the increment.
-    fn set_move_time(&mut self, position: &mut Position) {
-        if self.options.movetime.is_some() || self.options.infinite {
-            return;
-        }
-        let time_left_for_side = if position.turn == Color::White {
-            match self.options.wtime {
-                Some(wtime) => wtime,
-                None => 0,
-            }
-        } else {
-            match self.options.btime {
-                Some(btime) => btime,
-                None => 0,
-            }
-        };
-        let increment = if position.turn == Color::White {
-            match self.options.winc {
-                Some(winc) => winc,
-                None => 0,
-            }
-        } else {
-            match self.options.binc {
-                Some(binc) => binc,
-                None => 0,
-            }
-        };
-
-        let time_for_move = time_left_for_side / 45 + (increment / 2);
-
-        if time_for_move >= time_left_for_side {
-            self.options.movetime = Some(time_left_for_side - 500);
-        } else {
-            if time_for_move <= 0 {
-                self.options.movetime = Some(200);
-                return;
-            }
-            self.options.movetime = Some(time_for_move);
-        }
-    }
-
     pub fn get_best_move(
         &mut self,
         position: &mut Position,
@@ -186,38 +239,40 @@ impl Evaluator {
             best_move: None,
             depth: 0,
             ply: 0,
+            seldepth: 0,
             nodes: 0,
             cutoffs: Cutoffs::new(),
+            pv: Vec::new(),
         };
 
         let depth = match options.depth {
-            Some(depth) => depth as u8,
-            None => MAX_PLY as u8,
+            Some(depth) => depth.min(MAX_DEPTH),
+            None => MAX_DEPTH,
         };
 
         self.options = options;
-        self.set_move_time(position);
+        self.time_manager.init(&self.options, position.turn);
+        if let (Some(optimum_time), Some(maximum_time)) = (
+            self.time_manager.optimum_time,
+            self.time_manager.maximum_time,
+        ) {
+            self.report_message(&format!(
+                "optimum time {} ms maximum time {} ms",
+                optimum_time, maximum_time
+            ));
+        }
 
         self.running = true;
-        self.started_at = Evaluator::_get_time_ms();
+        self.started_at = Instant::now();
+        self.next_time_check = self.time_manager.get_nodes_between_polls() as i32;
 
-        let mut alpha = -50000;
-        let mut beta = 50000;
+        let mut previous_score: i32 = 0;
         let mut current_depth = 1;
         let mut pv_completed_so_far: Vec<BitPackedMove> = Vec::new();
         self.repetition_table.clear();
         self.pv_table = PVTable::new();
-
-        // Get a fallback move in case search doesn't complete
-        let legal_moves = position.generate_moves(false);
-        let mut fallback_move = chess::_move::BitPackedMove::default();
-        for m in legal_moves {
-            if position.make_move(m, false) {
-                position.unmake_move();
-                fallback_move = m;
-                break;
-            }
-        }
+        self.stack.clear();
+        self.init_root_moves(position, tt);
 
         tt.increment_age();
 
@@ -226,97 +281,194 @@ impl Evaluator {
                 break;
             }
 
-            let start_time = Evaluator::_get_time_ms();
+            let start_time = Instant::now();
+            self.result.seldepth = 0;
 
-            let score = self.negamax(position, alpha, beta, current_depth, false, None, tt);
+            // Aspiration windows: search a narrow window around the previous score, widening it
+            // on the side it fails on until the score falls inside
+            let mut delta = ASPIRATION_DELTA;
+            let (mut alpha, mut beta) = if current_depth >= ASPIRATION_MIN_DEPTH
+                && previous_score.abs() < MATE_IN_MAX_PLY
+            {
+                (
+                    (previous_score - delta).max(-INFINITY),
+                    (previous_score + delta).min(INFINITY),
+                )
+            } else {
+                (-INFINITY, INFINITY)
+            };
+            let mut failures = 0;
+
+            let score = loop {
+                let score = self.negamax(position, alpha, beta, current_depth, NodeType::Root, tt);
+                if !self.running {
+                    break score;
+                }
 
-            // If score falls outside aspiration window and we haven't already widened it
-            if (score <= alpha || score >= beta) && !(alpha == -50000 && beta == 50000) {
-                // Widen the window and re-search at same depth
-                alpha = -50000;
-                beta = 50000;
-                continue;
-            }
+                self.sort_root_moves();
+                if score > alpha && score < beta {
+                    break score;
+                }
 
-            alpha = score - 50;
-            beta = score + 50;
+                let bound = if score >= beta {
+                    tt::TranspositionTableEntryFlag::BETA
+                } else {
+                    tt::TranspositionTableEntryFlag::ALPHA
+                };
+                // The root PV is only complete when a move raised alpha and nothing beat beta,
+                // so start the reported line with the move the failed search ends on
+                let pv = match self.result.best_move {
+                    Some(best_move) if self.pv_table.get_pv().first() != Some(&best_move) => {
+                        if pv_completed_so_far.first() == Some(&best_move) {
+                            pv_completed_so_far.clone()
+                        } else {
+                            vec![best_move]
+                        }
+                    }
+                    _ => self.pv_table.get_pv(),
+                };
+                self.result.depth = current_depth;
+                self.report_iteration(score, bound, &pv, tt);
+
+                delta *= 2;
+                failures += 1;
+                if failures >= ASPIRATION_MAX_FAILURES {
+                    alpha = -INFINITY;
+                    beta = INFINITY;
+                } else if bound == tt::TranspositionTableEntryFlag::BETA {
+                    beta = (score + delta).min(INFINITY);
+                } else {
+                    alpha = (score - delta).max(-INFINITY);
+                }
+            };
 
-            // Save PV from this completed iteration
+            // An interrupted iteration may not have looked at the best move yet
+            if !self.running {
+                break;
+            }
+
+            previous_score = score;
             let pv = self.pv_table.get_pv();
             if !pv.is_empty() {
                 pv_completed_so_far = pv;
             }
 
-            if !self.running {
+            self.report_iteration(
+                score,
+                tt::TranspositionTableEntryFlag::EXACT,
+                &pv_completed_so_far,
+                tt,
+            );
+            current_depth += 1;
+
+            // Decide whether there is time for another iteration
+            if let Some(&best_move) = pv_completed_so_far.first() {
+                self.time_manager.update(best_move, score);
+            }
+            if !self.time_manager.should_start_iteration(
+                self.started_at.elapsed().as_millis(),
+                start_time.elapsed().as_millis(),
+            ) {
                 break;
             }
-
-            self.print_info(start_time, score, &pv_completed_so_far, tt);
-            current_depth += 1;
         }
 
-        let best_move = if !pv_completed_so_far.is_empty() {
-            pv_completed_so_far[0]
-        } else {
-            fallback_move
+        // The root moves are legal and searched best first, so even a search that was stopped
+        // before completing an iteration has a move to play, unless there are none
+        let best_move = match pv_completed_so_far.first() {
+            Some(&m) => Some(m),
+            None => self.root_moves.first().map(|root_move| root_move.m),
         };
 
-        // Verify the best move is legal before outputting
-        let final_move = if position.make_move(best_move, false) {
-            position.unmake_move();
-            best_move
-        } else {
-            // If best move is illegal, fall back to first legal move
-            fallback_move
-        };
+        // A stopped iteration may have left the score of a move it did not finish searching
+        if !pv_completed_so_far.is_empty() {
+            self.result.score = previous_score;
+            self.result.depth = current_depth - 1;
+        }
+        self.result.best_move = best_move;
+        self.result.pv = pv_completed_so_far;
 
-        if !self.silent {
-            println!("bestmove {}", final_move);
-            use std::io::Write;
-            std::io::stdout().flush().unwrap();
+        self.listener.on_info(SearchInfo::BestMove(best_move));
+        best_move
+    }
+
+    /// Collects the legal moves of the root position, in the order the move picker hands
+    /// them out.
+    fn init_root_moves(&mut self, position: &mut Position, tt: &TranspositionTable) {
+        self.root_moves.clear();
+
+        let side = position.turn;
+        let mut picker = MovePicker::new(
+            position,
+            self.get_tt_move(position, tt),
+            [BitPackedMove::default(); 2],
+            BitPackedMove::default(),
+        );
+        while let Some(m) = picker.next(position, |m| {
+            if m.is_capture() || m.is_enpassant() {
+                self.history.get_capture_score(m)
+            } else {
+                self.history
+                    .get_quiet_score(side, m, [BitPackedMove::default(); 2])
+            }
+        }) {
+            self.root_moves.push(RootMove { m, nodes: 0 });
         }
-        Some(final_move)
+    }
+
+    /// Puts the best move of the last search first, and the other root moves by the nodes spent
+    /// on them, as moves that took more effort to refute are more likely to become the best.
+    fn sort_root_moves(&mut self) {
+        let best_move = self.result.best_move;
+        self.root_moves
+            .sort_by_key(|root_move| (Some(root_move.m) != best_move, Reverse(root_move.nodes)));
+    }
+
+    /// Returns the moves of the root position, in the order they are searched.
+    pub fn get_root_moves(&self) -> &[RootMove] {
+        &self.root_moves
     }
 
     pub fn negamax(
         &mut self,
         position: &mut Position,
         _alpha: i32,
-        beta: i32,
+        _beta: i32,
         _depth: u8,
-        was_last_move_null: bool,
-        last_move: Option<BitPackedMove>,
+        node_type: NodeType,
         tt: &mut TranspositionTable,
     ) -> i32 {
         let mut alpha = _alpha;
+        let mut beta = _beta;
         let mut depth = _depth; // will be mutable later for search extensions
         let mut alpha_move = chess::_move::BitPackedMove::default();
+        let is_root = node_type == NodeType::Root;
+        let is_pv_node = node_type != NodeType::NonPV;
 
         // Prevent stack overflow from deep recursion
         if self.result.ply >= MAX_PLY as u32 - 1 {
             return self.evaluate(position);
         }
 
-        if self.result.nodes & 2047 == 0 {
+        // Nodes that return before searching a move leave no PV for their parent to copy
+        self.pv_table.clear_at(self.result.ply as usize);
+
+        if self.result.nodes >= self.next_time_check {
             self.running = self.check_time();
         }
 
-        let is_in_check = position.is_in_check();
-
-        if !is_in_check && depth > 2 && !was_last_move_null {
-            position.make_null_move();
-            self.result.ply += 1;
-
-            let null_move_score = -self.negamax(position, -beta, -beta + 1, depth - 3, true, None, tt);
-
-            position.unmake_move();
-            self.result.ply -= 1;
-
-            if null_move_score >= beta {
-                return beta;
+        // Mate distance pruning: no line from here can beat mating sooner or being mated later
+        // than is already possible closer to the root
+        if !is_root {
+            alpha = alpha.max(-MATE + self.result.ply as i32);
+            beta = beta.min(MATE - self.result.ply as i32 - 1);
+            if alpha >= beta {
+                return alpha;
             }
         }
 
+        let is_in_check = position.is_in_check();
+
         if is_in_check {
             depth += 1;
         }
@@ -332,83 +484,330 @@ impl Evaluator {
         }
 
         self.result.nodes += 1;
+        self.result.seldepth = self.result.seldepth.max(self.result.ply);
+
+        let ply = self.result.ply as usize;
+        // The move that led here, the parent records a null move when it passes
+        let last_move = self.stack.get_previous_moves(ply)[0];
+        let was_last_move_null = ply > 0 && last_move == BitPackedMove::default();
+        let excluded_move = self.stack[ply].excluded_move;
+        let is_exclusion_search = excluded_move != BitPackedMove::default();
+        let tt_entry = tt.probe_entry(position.hash, depth, alpha, beta, ply);
+
+        // The stored result covers the excluded move, so it can't answer an exclusion search, and
+        // PV nodes are searched anyway to report their full PV
+        if tt_entry.is_valid() && !is_exclusion_search && !is_pv_node {
+            return tt_entry.get_value(ply);
+        }
 
-        let tt_entry = tt.probe_entry(position.hash, depth, alpha, beta);
+        if depth == 0 {
+            return self.quiescence(position, alpha, beta, true, tt);
+        }
 
-        if tt_entry.is_valid() {
-            if tt_entry.get_flag() == tt::TranspositionTableEntryFlag::EXACT && self.result.ply == 0
-            {
-                self.result.depth = depth;
-                self.result.score = tt_entry.get_value();
+        // Pruning decisions use the static evaluation corrected for its known errors in this
+        // pawn structure, the table keeps the evaluation itself
+        let (raw_eval, static_eval) = if is_in_check {
+            (NO_STATIC_EVAL, NO_STATIC_EVAL)
+        } else {
+            let raw_eval = self.evaluate(position);
+            let static_eval =
+                self.correction_history
+                    .correct(position.turn, position.pawn_hash, raw_eval);
+            (raw_eval, static_eval)
+        };
+        self.stack[ply].static_eval = static_eval;
+
+        // Whether the position got better for us since our last move
+        let improving = !is_in_check
+            && (ply < 2
+                || self.stack[ply - 2].static_eval == NO_STATIC_EVAL
+                || static_eval > self.stack[ply - 2].static_eval);
+
+        // Static pruning is only safe away from the PV, out of check and far from mate scores
+        let can_prune = !is_pv_node && !is_in_check && !is_exclusion_search;
+
+        // Internal iterative reduction: a PV node without a move from the table has no good
+        // guess to search first, so search it shallower and let the next iteration find one
+        let tt_move = self.get_tt_move(position, tt);
+        if is_pv_node
+            && depth >= IIR_MIN_DEPTH
+            && tt_move == BitPackedMove::default()
+            && !is_exclusion_search
+        {
+            depth -= 1;
+        }
+
+        // Reverse futility pruning, the opponent's last move left us too far ahead
+        if can_prune
+            && depth <= RFP_DEPTH
+            && beta.abs() < MATE_IN_MAX_PLY
+            && static_eval - RFP_MARGIN * (depth as i32 - improving as i32) >= beta
+        {
+            return beta;
+        }
+
+        // Razoring, drop into quiescence when even a good quiet move can't reach alpha
+        if can_prune
+            && depth <= RAZORING_DEPTH
+            && alpha.abs() < MATE_IN_MAX_PLY
+            && static_eval + RAZORING_MARGIN * depth as i32 <= alpha
+        {
+            let score = self.quiescence(position, alpha, beta, true, tt);
+            if score <= alpha {
+                return score;
             }
-            return tt_entry.get_value();
         }
 
-        if depth == 1 {
-            let static_evaluation = self.evaluate(position);
-            if (static_evaluation + FUTILITY_MARGIN) < alpha {
-                return self.quiescence(position, alpha, beta, tt);
+        // Null move pruning, if passing still beats beta a real move will too. Passing is not
+        // an option in zugzwang, which is likely when only pawns are left.
+        if can_prune
+            && !was_last_move_null
+            && ply >= self.null_move_min_ply
+            && depth >= NMP_MIN_DEPTH
+            && beta.abs() < MATE_IN_MAX_PLY
+            && static_eval >= beta
+            && self.has_non_pawn_material(position)
+        {
+            let eval_reduction =
+                ((static_eval - beta) / NMP_EVAL_DIVISOR).min(NMP_MAX_EVAL_REDUCTION);
+            let reduction = NMP_BASE_REDUCTION + depth / NMP_DEPTH_DIVISOR + eval_reduction as u8;
+            let null_depth = depth.saturating_sub(reduction);
+
+            self.stack[ply].current_move = BitPackedMove::default();
+            position.make_null_move();
+            self.result.ply += 1;
+
+            let mut null_move_score =
+                -self.negamax(position, -beta, -beta + 1, null_depth, NodeType::NonPV, tt);
+
+            position.unmake_move();
+            self.result.ply -= 1;
+
+            if !self.running {
+                return 0;
+            }
+
+            if null_move_score >= beta {
+                // Mates found after passing are not proven
+                if null_move_score >= MATE_IN_MAX_PLY {
+                    null_move_score = beta;
+                }
+
+                if depth < NMP_VERIFICATION_DEPTH {
+                    return null_move_score;
+                }
+
+                // Verify the cutoff with null moves disabled for most of the remaining depth, then
+                // restore the limit of any verification this one runs inside
+                let saved_null_move_min_ply = self.null_move_min_ply;
+                self.null_move_min_ply = ply + 3 * null_depth as usize / 4;
+                let score = self.negamax(position, beta - 1, beta, null_depth, NodeType::NonPV, tt);
+                self.null_move_min_ply = saved_null_move_min_ply;
+
+                if score >= beta {
+                    return null_move_score;
+                }
             }
         }
 
-        if depth == 0 {
-            return self.quiescence(position, alpha, beta, tt);
+        // Singular extensions, when the transposition table move is much better than every
+        // other move, search it deeper
+        let mut singular_move = BitPackedMove::default();
+        let mut singular_extension = 0;
+        if let Some(entry) = tt.probe(position.hash) {
+            let tt_value = entry.get_value(ply);
+            if !is_root
+                && !is_exclusion_search
+                && depth >= SE_MIN_DEPTH
+                && entry.get_move() != BitPackedMove::default()
+                && entry.get_flag() != tt::TranspositionTableEntryFlag::ALPHA
+                && entry.get_depth() + SE_TT_DEPTH_MARGIN >= depth
+                && tt_value.abs() < MATE_IN_MAX_PLY
+            {
+                let singular_beta = tt_value - SE_MARGIN * depth as i32;
+                let singular_depth = (depth - 1) / 2;
+
+                self.stack[ply].excluded_move = entry.get_move();
+                let score = self.negamax(
+                    position,
+                    singular_beta - 1,
+                    singular_beta,
+                    singular_depth,
+                    NodeType::NonPV,
+                    tt,
+                );
+                self.stack[ply].excluded_move = BitPackedMove::default();
+
+                if !self.running {
+                    return 0;
+                }
+
+                singular_move = entry.get_move();
+                if score < singular_beta {
+                    singular_extension = 1;
+                } else if singular_beta >= beta {
+                    // Multi-cut, another move beats beta too, so this node most likely fails high
+                    return singular_beta;
+                } else if tt_value >= beta {
+                    // Another move is about as good, so the TT move doesn't need the full depth
+                    singular_extension = -1;
+                }
+            }
         }
 
+        let futility_margin = FUTILITY_MARGIN + FUTILITY_DEPTH_MARGIN * depth as i32;
+        let late_move_count = (3 + depth as usize * depth as usize) / (2 - improving as usize);
+
         let mut legal_moves_searched = 0;
-        let mut queue = self.order_moves_p(position.generate_moves(false), position, last_move, tt);
-        let mut found_pv = false;
+        let mut quiets_searched = MoveList::new();
+        let mut captures_searched = MoveList::new();
+        let side = position.turn;
+        let previous_moves = self.stack.get_previous_moves(ply);
+        let counter_move = if last_move != BitPackedMove::default() {
+            self.counter_move_table[last_move.get_from() as usize][last_move.get_to() as usize]
+        } else {
+            BitPackedMove::default()
+        };
+        let mut picker = MovePicker::new(position, tt_move, self.stack[ply].killers, counter_move);
         let mut hash_f = tt::TranspositionTableEntryFlag::ALPHA;
+        let mut root_index = 0;
 
-        // Clear PV at this ply
-        self.pv_table.clear_at(self.result.ply as usize);
+        loop {
+            // The root searches its own list of moves, ordered by the previous iterations
+            let m = if is_root {
+                match self.root_moves.get(root_index) {
+                    Some(root_move) => root_move.m,
+                    None => break,
+                }
+            } else {
+                let next = picker.next(position, |m| {
+                    if m.is_capture() || m.is_enpassant() {
+                        self.history.get_capture_score(m)
+                    } else {
+                        self.history.get_quiet_score(side, m, previous_moves)
+                    }
+                });
+                match next {
+                    Some(m) => m,
+                    None => break,
+                }
+            };
+            root_index += 1;
+
+            // Show progress through the root moves on long searches
+            if is_root && self.started_at.elapsed().as_millis() >= CURRMOVE_DELAY {
+                self.listener.on_info(SearchInfo::CurrentMove {
+                    depth,
+                    m,
+                    number: root_index,
+                });
+            }
+
+            if m == excluded_move {
+                continue;
+            }
 
-        while let Some(pm) = queue.pop() {
-            let is_legal_move = position.make_move(pm.m, false);
-            if !is_legal_move {
+            let is_quiet = !m.is_capture() && !m.is_enpassant();
+            let can_prune_move = legal_moves_searched > 0
+                && !is_in_check
+                && is_quiet
+                && !m.is_promotion()
+                && alpha > -MATE_IN_MAX_PLY;
+
+            // Quiet moves that hang the moving piece at low depth
+            let is_losing_quiet = can_prune_move
+                && depth <= SEE_PRUNING_DEPTH
+                && !position.see_ge(m, -SEE_QUIET_MARGIN * depth as i32);
+
+            // Late quiet moves, quiet moves that can't raise alpha, and quiet moves that have
+            // been bad so far, away from the PV
+            let history = if is_quiet {
+                self.history.get_quiet_score(side, m, previous_moves)
+            } else {
+                0
+            };
+            let is_futile_quiet = can_prune_move
+                && !is_pv_node
+                && ((depth <= LMP_DEPTH && legal_moves_searched >= late_move_count)
+                    || (depth <= FUTILITY_DEPTH && static_eval + futility_margin <= alpha)
+                    || (depth <= HISTORY_PRUNING_DEPTH
+                        && history < -HISTORY_PRUNING_MARGIN * depth as i32));
+
+            position.make_legal_move(m);
+
+            // Prune them unless they give check
+            if (is_losing_quiet || is_futile_quiet) && !position.is_in_check() {
+                position.unmake_move();
                 continue;
             }
 
+            self.stack[ply].current_move = m;
             self.result.ply += 1;
             self.repetition_table.push(position.hash);
+            let nodes_before = self.result.nodes;
 
-            let mut _score = 0;
-
-            // If we have found a pv move, then we need to search it with a null window
-            if found_pv {
-                _score = -self.negamax(position, -alpha - 1, -alpha, depth - 1, false, None, tt);
-
-                // If our pv move fails, then we need to search again with a full window
-                if (_score > alpha) && (_score < beta) {
-                    _score = -self.negamax(position, -beta, -alpha, depth - 1, false, None, tt);
-                }
+            let gives_check = position.is_in_check();
+            let new_depth = if m == singular_move {
+                (depth as i32 - 1 + singular_extension) as u8
             } else {
-                if legal_moves_searched == 0 {
-                    // If we have not found a pv move, and this is the first move, then we need to search with a full window
-                    _score = -self.negamax(position, -beta, -alpha, depth - 1, false, None, tt);
+                depth - 1
+            };
+            let mut _score;
+
+            if legal_moves_searched == 0 {
+                // The first move is expected to be the best, search it with a full window
+                let child_type = if is_pv_node {
+                    NodeType::PV
                 } else {
-                    if legal_moves_searched >= _FULL_DEPTH_MOVES
-                        && depth >= _REDUCTION_LIMIT
-                        && !position.is_in_check()
-                        && !pm.m.is_promotion()
-                        && !pm.m.is_capture()
-                    {
-                        _score =
-                            -self.negamax(position, -alpha - 1, -alpha, depth - 2, false, None, tt);
-                    } else {
-                        _score = alpha + 1;
+                    NodeType::NonPV
+                };
+                _score = -self.negamax(position, -beta, -alpha, new_depth, child_type, tt);
+            } else {
+                let mut reduction = 0;
+                if depth >= LMR_MIN_DEPTH
+                    && legal_moves_searched >= LMR_MIN_MOVES
+                    && is_quiet
+                    && !m.is_promotion()
+                    && !is_in_check
+                {
+                    let mut r = self.get_reduction(depth, legal_moves_searched) as i32;
+                    if is_pv_node {
+                        r -= 1;
+                    }
+                    if !improving {
+                        r += 1;
+                    }
+                    if gives_check {
+                        r -= 1;
+                    }
+                    if self.stack[ply].killers.contains(&m) || m == counter_move {
+                        r -= 1;
                     }
+                    r -= (history / LMR_HISTORY_DIVISOR).clamp(-2, 2);
 
-                    // If we found a better move during LMR
-                    if _score > alpha {
-                        _score =
-                            -self.negamax(position, -alpha - 1, -alpha, depth - 1, false, None, tt);
+                    // Never reduce straight into quiescence
+                    reduction = r.clamp(0, new_depth as i32 - 1) as u8;
+                }
 
-                        // if our LMR move fails, then we need to search again with a full window
-                        if (_score > alpha) && (_score < beta) {
-                            _score = -self.negamax(position, -beta, -alpha, depth - 1, false, None, tt);
-                        }
-                    }
+                // Later moves are expected to fail low, prove it with a null window
+                _score = -self.negamax(
+                    position,
+                    -alpha - 1,
+                    -alpha,
+                    new_depth - reduction,
+                    NodeType::NonPV,
+                    tt,
+                );
+
+                // The reduced search beat alpha, verify it at full depth
+                if _score > alpha && reduction > 0 {
+                    _score =
+                        -self.negamax(position, -alpha - 1, -alpha, new_depth, NodeType::NonPV, tt);
+                }
+
+                // The move might be a new best move, search it again with a full window
+                if is_pv_node && _score > alpha && _score < beta {
+                    _score = -self.negamax(position, -beta, -alpha, new_depth, NodeType::PV, tt);
                 }
             }
 
@@ -416,28 +815,66 @@ impl Evaluator {
             self.repetition_table.pop();
             position.unmake_move();
 
+            if is_root {
+                self.root_moves[root_index - 1].nodes += (self.result.nodes - nodes_before) as u64;
+            }
+
             if !self.running {
                 return 0;
             }
 
             legal_moves_searched += 1;
+            if is_quiet {
+                quiets_searched.push(m);
+            } else {
+                captures_searched.push(m);
+            }
 
             if _score >= beta {
-                tt.save(
-                    position.hash,
-                    depth,
-                    tt::TranspositionTableEntryFlag::BETA,
-                    beta,
-                    pm.m,
-                );
+                if !is_exclusion_search {
+                    tt.save(
+                        position.hash,
+                        depth,
+                        tt::TranspositionTableEntryFlag::BETA,
+                        tt::TranspositionTable::score_to_tt(beta, ply),
+                        raw_eval,
+                        m,
+                    );
+
+                    // Beta is a lower bound, it only shows the evaluation was too low
+                    if is_quiet && !is_in_check && beta > static_eval {
+                        self.update_correction(position, depth, raw_eval, beta);
+                    }
+                }
 
-                if self.killer_moves[0][self.result.ply as usize] != pm.m {
-                    self.killer_moves[1][self.result.ply as usize] =
-                        self.killer_moves[0][self.result.ply as usize];
-                    self.killer_moves[0][self.result.ply as usize] = pm.m;
+                // Reward the move and penalize the ones that were searched before it in vain
+                if is_quiet {
+                    self.history.update_quiets(
+                        side,
+                        m,
+                        quiets_searched.as_slice(),
+                        previous_moves,
+                        depth,
+                    );
+                    self.history.update_captures(
+                        BitPackedMove::default(),
+                        captures_searched.as_slice(),
+                        depth,
+                    );
+                } else {
+                    self.history
+                        .update_captures(m, captures_searched.as_slice(), depth);
                 }
 
-                self.counter_move_table[pm.m.get_from() as usize][pm.m.get_to() as usize] = pm.m;
+                if is_quiet {
+                    self.stack.add_killer(ply, m);
+
+                    // Remember the quiet refutation of the opponent's last move
+                    if last_move != BitPackedMove::default() {
+                        self.counter_move_table[last_move.get_from() as usize]
+                            [last_move.get_to() as usize] = m;
+                    }
+                }
 
                 // Cutoff tracking
                 if legal_moves_searched == 1 {
@@ -449,43 +886,94 @@ impl Evaluator {
                 self.result.cutoffs.avg_cutoff_move_no += legal_moves_searched as f32;
                 self.result.cutoffs.total += 1;
 
+                if is_root {
+                    self.result.best_move = Some(m);
+                    self.listener.on_info(SearchInfo::NewBestMove {
+                        depth,
+                        m,
+                        score: beta,
+                    });
+                }
+
                 return beta;
             }
 
             if _score > alpha {
                 hash_f = tt::TranspositionTableEntryFlag::EXACT;
-                alpha_move = pm.m;
-                found_pv = true;
+                alpha_move = m;
                 alpha = _score;
 
                 // Update PV table
-                self.pv_table.update(self.result.ply as usize, pm.m);
+                self.pv_table.update(self.result.ply as usize, m);
 
-                if self.result.ply == 0 {
+                if is_root {
                     self.result.depth = depth;
                     self.result.score = _score;
-                    self.result.best_move = Some(pm.m);
+                    self.result.best_move = Some(m);
+                    self.listener.on_info(SearchInfo::NewBestMove {
+                        depth,
+                        m,
+                        score: _score,
+                    });
                 }
-
-                self.history_moves[pm.m.get_piece() as usize][self.result.ply as usize] +=
-                    (depth * depth) as u32;
             }
         }
 
+        // Exclusion searches only tell whether the other moves fail low
+        if is_exclusion_search {
+            return alpha;
+        }
+
         if legal_moves_searched == 0 {
             if is_in_check {
-                alpha = -49000 + self.result.ply as i32;
+                alpha = -MATE + self.result.ply as i32;
             } else {
                 alpha = 0;
             }
         }
 
-        tt.save(position.hash, depth, hash_f, alpha, alpha_move);
+        tt.save(
+            position.hash,
+            depth,
+            hash_f,
+            tt::TranspositionTable::score_to_tt(alpha, ply),
+            raw_eval,
+            alpha_move,
+        );
+
+        // Scores that depend on a capture tell more about the capture than about the position,
+        // and a fail low only shows the evaluation was too high
+        let best_is_quiet = !alpha_move.is_capture() && !alpha_move.is_enpassant();
+        if !is_in_check
+            && best_is_quiet
+            && (hash_f == tt::TranspositionTableEntryFlag::EXACT || alpha < static_eval)
+        {
+            self.update_correction(position, depth, raw_eval, alpha);
+        }
 
         alpha
     }
 
-    fn quiescence(&mut self, position: &mut Position, _alpha: i32, beta: i32, tt: &mut TranspositionTable) -> i32 {
+    /// Teaches the correction history the search result of a node, unless it is a mate score.
+    fn update_correction(&mut self, position: &Position, depth: u8, raw_eval: i32, score: i32) {
+        if score.abs() >= MATE_IN_MAX_PLY {
+            return;
+        }
+        self.correction_history
+            .update(position.turn, position.pawn_hash, depth, raw_eval, score);
+    }
+
+    /// Searches captures until the position is quiet, so that positions are only evaluated once
+    /// nothing hangs. Every move is searched when in check, and on its first ply, when
+    /// `with_checks` is set, the quiet moves that give check are searched too.
+    fn quiescence(
+        &mut self,
+        position: &mut Position,
+        _alpha: i32,
+        beta: i32,
+        with_checks: bool,
+        tt: &mut TranspositionTable,
+    ) -> i32 {
         let mut alpha = _alpha;
 
         // Prevent stack overflow from deep recursion
@@ -493,33 +981,100 @@ impl Evaluator {
             return self.evaluate(position);
         }
 
-        if self.result.nodes & 2047 == 0 {
+        if self.result.nodes >= self.next_time_check {
             self.running = self.check_time();
         }
 
         self.result.nodes += 1;
+        self.result.seldepth = self.result.seldepth.max(self.result.ply);
 
-        let stand_pat = self.evaluate(position);
-        if stand_pat >= beta {
-            return beta;
+        let ply = self.result.ply as usize;
+        let tt_entry = tt.probe_entry(position.hash, 0, alpha, beta, ply);
+        if tt_entry.is_valid() {
+            return tt_entry.get_value(ply);
         }
-        if alpha < stand_pat {
-            alpha = stand_pat
+
+        // There is no standing pat in check, every evasion has to be searched
+        let is_in_check = position.is_in_check();
+        let stand_pat = if is_in_check {
+            NO_STATIC_EVAL
+        } else {
+            self.evaluate(position)
+        };
+        if !is_in_check {
+            if stand_pat >= beta {
+                tt.save(
+                    position.hash,
+                    0,
+                    tt::TranspositionTableEntryFlag::BETA,
+                    tt::TranspositionTable::score_to_tt(beta, ply),
+                    stand_pat,
+                    BitPackedMove::default(),
+                );
+                return beta;
+            }
+            if alpha < stand_pat {
+                alpha = stand_pat
+            }
         }
 
-        let mut queue = self.order_moves_p(position.generate_moves(true), position, None, tt);
-        while let Some(pm) = queue.pop() {
-            if !pm.m.is_capture() {
-                continue;
+        let side = position.turn;
+        let previous_moves = self.stack.get_previous_moves(ply);
+        let tt_move = self.get_tt_move(position, tt);
+        let mut picker = if is_in_check {
+            MovePicker::new(
+                position,
+                tt_move,
+                [BitPackedMove::default(); 2],
+                BitPackedMove::default(),
+            )
+        } else {
+            // Only captures that do not lose material are searched, and quiet checks
+            MovePicker::new_quiescence(position, tt_move, with_checks)
+        };
+        let mut legal_moves_searched = 0;
+        let mut best_move = BitPackedMove::default();
+        let mut hash_f = tt::TranspositionTableEntryFlag::ALPHA;
+
+        while let Some(m) = picker.next(position, |m| {
+            if m.is_capture() || m.is_enpassant() {
+                self.history.get_capture_score(m)
+            } else {
+                self.history.get_quiet_score(side, m, previous_moves)
+            }
+        }) {
+            let is_quiet = !m.is_capture() && !m.is_enpassant();
+
+            if !is_in_check {
+                // Delta pruning: even winning the captured piece for free can't reach alpha
+                if !is_quiet && !m.is_promotion() {
+                    let captured = if m.is_enpassant() {
+                        Piece::WhitePawn
+                    } else {
+                        m.get_capture()
+                    };
+                    let gain = SEE_PIECE_VALUES[captured as usize % 6];
+                    if stand_pat + gain + DELTA_MARGIN <= alpha {
+                        continue;
+                    }
+                }
+
+                if is_quiet && !position.see_ge(m, 0) {
+                    continue;
+                }
             }
 
-            let is_legal_capture = position.make_move(pm.m, true);
-            if !is_legal_capture {
+            position.make_legal_move(m);
+
+            // Quiet moves are only searched for the checks among them
+            if !is_in_check && is_quiet && !position.is_in_check() {
+                position.unmake_move();
                 continue;
             }
 
+            self.stack[ply].current_move = m;
             self.result.ply += 1;
-            let score = -self.quiescence(position, -beta, -alpha, tt);
+            let score = -self.quiescence(position, -beta, -alpha, false, tt);
             self.result.ply -= 1;
 
             position.unmake_move();
@@ -528,86 +1083,137 @@ impl Evaluator {
                 return 0;
             }
 
+            legal_moves_searched += 1;
+
             if score >= beta {
+                tt.save(
+                    position.hash,
+                    0,
+                    tt::TranspositionTableEntryFlag::BETA,
+                    tt::TranspositionTable::score_to_tt(beta, ply),
+                    stand_pat,
+                    m,
+                );
                 return beta;
             }
 
             if score > alpha {
+                hash_f = tt::TranspositionTableEntryFlag::EXACT;
+                best_move = m;
                 alpha = score;
             }
         }
 
-        alpha
-    }
-
-    pub fn print_info(&self, start_time: u128, score: i32, pv_line: &[BitPackedMove], tt: &TranspositionTable) {
-        let stop_time: u128 = Evaluator::_get_time_ms();
-        let nps: i32 =
-            (self.result.nodes as f64 / ((stop_time - start_time) as f64 / 1000.0)) as i32;
-
-        if !pv_line.is_empty() {
-            let is_mate = score > 48000;
-            let mut mate_in: i32 = 0;
-
-            if is_mate {
-                let x = -(score - 49000);
-                if x % 2 == 0 {
-                    mate_in = x as i32 / 2;
-                } else {
-                    mate_in = (x as i32 + 1) / 2;
-                }
-            }
+        if is_in_check && legal_moves_searched == 0 {
+            return -MATE + ply as i32;
+        }
 
-            if !self.silent {
-                print!(
-                    "info score {} {} depth {} nodes {} nps {} time {} hashfull {}",
-                    if is_mate { "mate" } else { "cp" },
-                    if is_mate { mate_in } else { score },
-                    self.result.depth,
-                    self.result.nodes,
-                    nps,
-                    stop_time - start_time,
-                    tt.get_hashfull()
-                );
+        // Standing pat is the exact score when no move improves on it
+        if !is_in_check && stand_pat > _alpha {
+            hash_f = tt::TranspositionTableEntryFlag::EXACT;
+        }
 
-                let mut pv_str: String = String::new();
+        tt.save(
+            position.hash,
+            0,
+            hash_f,
+            tt::TranspositionTable::score_to_tt(alpha, ply),
+            stand_pat,
+            best_move,
+        );
 
-                for m in pv_line {
-                    pv_str.push_str(" ");
-                    pv_str.push_str(m.to_string().as_str());
-                }
+        alpha
+    }
 
-                println!(" pv{}", pv_str);
-                use std::io::Write;
-                std::io::stdout().flush().unwrap();
-            }
+    /// Returns the number of moves until mate for mate scores, negative when getting mated.
+    pub fn get_mate_in(score: i32) -> Option<i32> {
+        if score >= MATE_IN_MAX_PLY {
+            Some((MATE - score + 1) / 2)
+        } else if score <= -MATE_IN_MAX_PLY {
+            Some(-(MATE + score) / 2)
+        } else {
+            None
         }
     }
 
-    fn check_time(&self) -> bool {
-        if self.is_stopped() {
-            return false;
+    /// Returns the expected result in permille as wins, draws and losses for a score, from a
+    /// logistic model where WDL_MIDPOINT is an even chance to win.
+    pub fn get_wdl(score: i32) -> (i32, i32, i32) {
+        if let Some(mate_in) = Evaluator::get_mate_in(score) {
+            return if mate_in > 0 {
+                (1000, 0, 0)
+            } else {
+                (0, 0, 1000)
+            };
         }
 
-        if self.options.infinite {
-            return true;
+        let probability = |score: i32| {
+            let x = (WDL_MIDPOINT - score) as f64 / WDL_SCALE;
+            (1000.0 / (1.0 + x.exp())).round() as i32
+        };
+        let win = probability(score);
+        let loss = probability(-score);
+        (win, 1000 - win - loss, loss)
+    }
+
+    /// Reports the result of an iteration. The bound is the kind of score, the score of a search
+    /// that failed high or low being a lower or an upper bound. Nodes, time and nps count from
+    /// the start of the search.
+    pub fn report_iteration(
+        &mut self,
+        score: i32,
+        bound: tt::TranspositionTableEntryFlag,
+        pv_line: &[BitPackedMove],
+        tt: &TranspositionTable,
+    ) {
+        if pv_line.is_empty() {
+            return;
         }
 
-        let elapsed: u128 = Evaluator::_get_time_ms() - self.started_at;
+        let elapsed = self.started_at.elapsed();
+        let nps = (self.result.nodes as f64 / elapsed.as_secs_f64().max(0.001)) as u64;
+        let bound = match bound {
+            tt::TranspositionTableEntryFlag::BETA => Bound::Lower,
+            tt::TranspositionTableEntryFlag::ALPHA => Bound::Upper,
+            _ => Bound::Exact,
+        };
 
-        match self.options.movetime {
-            Some(movetime) => {
-                if (elapsed + 200) >= movetime as u128 {
-                    return false;
-                }
-            }
-            None => {}
-        }
+        self.listener.on_info(SearchInfo::Iteration(IterationInfo {
+            depth: self.result.depth,
+            seldepth: self.result.seldepth,
+            score,
+            mate_in: Evaluator::get_mate_in(score),
+            bound,
+            wdl: self.show_wdl.then(|| Evaluator::get_wdl(score)),
+            nodes: self.result.nodes as u64,
+            nps,
+            tbhits: 0,
+            time: elapsed.as_millis(),
+            hashfull: tt.get_hashfull(),
+            pv: pv_line.to_vec(),
+        }));
+    }
 
-        return true;
+    /// Reports a message for the user of the engine, which GUIs show as is.
+    pub fn report_message(&mut self, message: &str) {
+        self.listener
+            .on_info(SearchInfo::Message(message.to_string()));
     }
 
-    fn _has_non_pawn_material(&self, position: &mut Position) -> bool {
+    /// Returns false once the search has to stop, and schedules the next check of the clock.
+    fn check_time(&mut self) -> bool {
+        let elapsed = self.started_at.elapsed().as_millis();
+        self.time_manager
+            .adapt_poll_interval(self.result.nodes as u64, elapsed);
+        self.next_time_check = self
+            .result
+            .nodes
+            .saturating_add(self.time_manager.get_nodes_between_polls() as i32);
+
+        !self.is_stopped() && !self.time_manager.is_time_up(elapsed)
+    }
+
+    fn has_non_pawn_material(&self, position: &mut Position) -> bool {
         if position.turn == Color::White {
             (position.bitboards[Piece::WhiteBishop as usize]
                 + position.bitboards[Piece::WhiteKnight as usize]
@@ -623,144 +1229,180 @@ impl Evaluator {
         }
     }
 
-    pub fn _get_time_ms() -> u128 {
-        let now: std::time::SystemTime = std::time::SystemTime::now();
-        let since_the_epoch: std::time::Duration = now
-            .duration_since(std::time::UNIX_EPOCH)
-            .expect("Time went backwards");
-        return since_the_epoch.as_millis();
-    }
-
-    /// Returns a score for a move based on various heuristics.
-    fn get_move_priority(
-        &mut self,
-        m: chess::_move::BitPackedMove,
-        is_following_pv_line: bool,
-        last_move: Option<BitPackedMove>,
-    ) -> u32 {
-        if let Some(m) = last_move {
-            let counter_move = self.counter_move_table[m.get_from() as usize][m.get_to() as usize];
-            if counter_move != BitPackedMove::default() {
-                return 30000;
-            }
-        }
-
-        if is_following_pv_line {
-            return 20000;
+    /// Returns the move stored for this position by an earlier search, whatever the bound of its
+    /// score, or the null move when there is none.
+    fn get_tt_move(&self, position: &Position, tt: &TranspositionTable) -> BitPackedMove {
+        match tt.probe(position.hash) {
+            Some(tt_entry) => tt_entry.get_move(),
+            None => BitPackedMove::default(),
         }
+    }
 
-        if m.is_capture() {
-            return _MVV_LVA[m.get_piece() as usize][m.get_capture() as usize] + 1000;
-        }
+    pub fn evaluate(&mut self, position: &mut Position) -> i32 {
+        let material_score = position.material[position.turn as usize]
+            - position.material[(!position.turn) as usize];
 
-        if self.killer_moves[0][self.result.ply as usize] == m {
-            return 9001;
-        }
+        return material_score
+            + self.evaluate_pawn_structure(position)
+            + self.evaluate_open_files(position)
+            + self.evaluate_king_safety(position);
+    }
 
-        if self.killer_moves[1][self.result.ply as usize] == m {
-            return 9000;
-        }
+    /// Returns how far the game is from the endgame, between 0 (endgame) and
+    /// `OPENING_GAME_PHASE_SCORE - ENDGAME_PHASE_SCORE` (opening).
+    fn get_phase(position: &Position) -> i32 {
+        (position.get_game_phase_score() - ENDGAME_PHASE_SCORE)
+            .clamp(0, OPENING_GAME_PHASE_SCORE - ENDGAME_PHASE_SCORE)
+    }
 
-        return self.history_moves[m.get_piece() as usize][self.result.ply as usize] as u32;
+    /// Interpolates between a middlegame and endgame score using the given phase.
+    fn taper(mg_score: i32, eg_score: i32, phase: i32) -> i32 {
+        let range = OPENING_GAME_PHASE_SCORE - ENDGAME_PHASE_SCORE;
+        (mg_score * phase + eg_score * (range - phase)) / range
     }
 
-    fn order_moves_p(
-        &mut self,
-        moves: Vec<chess::_move::BitPackedMove>,
-        position: &mut Position,
-        last_move: Option<BitPackedMove>,
-        tt: &TranspositionTable,
-    ) -> BinaryHeap<PrioritizedMove> {
-        let mut queue: BinaryHeap<PrioritizedMove> = BinaryHeap::with_capacity(moves.len());
+    /// Returns the pawn structure score from the perspective of the side to move. The
+    /// pawn-only part of the evaluation is cached in the pawn hash table.
+    pub fn evaluate_pawn_structure(&mut self, position: &mut Position) -> i32 {
+        let entry = match self.pawn_table.probe(position.pawn_hash) {
+            Some(entry) => entry,
+            None => {
+                let entry = Evaluator::evaluate_pawns(position);
+                self.pawn_table.store(entry);
+                entry
+            }
+        };
 
-        let mut tt_move: chess::_move::BitPackedMove = chess::_move::BitPackedMove::default();
-        if let Some(tt_entry) = tt.get(position.hash) {
-            if tt_entry.get_move() != chess::_move::BitPackedMove::default()
-                && tt_entry.get_flag() == tt::TranspositionTableEntryFlag::EXACT
-            {
-                tt_move = tt_entry.get_move();
+        // Passed pawns that are free to advance, this depends on the pieces so isn't cached
+        let mut eg_score = entry.eg_score;
+        for side in [Color::White, Color::Black] {
+            let mut passed_pawns = entry.passed_pawns[side as usize];
+            while passed_pawns != 0 {
+                let square = utils::pop_lsb(&mut passed_pawns) as usize;
+                let (stop_square, rank) = if side == Color::White {
+                    (square - 8, GET_RANK[square] as usize)
+                } else {
+                    (square + 8, 7 - GET_RANK[square] as usize)
+                };
+
+                if utils::get_bit(position.occupancies[2], stop_square as u8) == 0 {
+                    eg_score += FREE_PASSER_BONUS_EG[rank] * (1 - 2 * side as i32);
+                }
             }
         }
 
-        for m in moves {
-            let priority: u32 = self.get_move_priority(m, m == tt_move, last_move);
-            queue.push(PrioritizedMove { m, priority })
+        let score = Evaluator::taper(entry.mg_score, eg_score, Evaluator::get_phase(position));
+
+        if position.turn == Color::White {
+            score
+        } else {
+            -score
         }
+    }
 
-        queue
+    /// Evaluates the pawn structure of both sides, returning a white-relative pawn hash entry.
+    fn evaluate_pawns(position: &Position) -> PawnHashEntry {
+        let (white_mg, white_eg, white_passed) =
+            Evaluator::evaluate_pawns_for(position, Color::White);
+        let (black_mg, black_eg, black_passed) =
+            Evaluator::evaluate_pawns_for(position, Color::Black);
+
+        PawnHashEntry {
+            key: position.pawn_hash,
+            mg_score: white_mg - black_mg,
+            eg_score: white_eg - black_eg,
+            passed_pawns: [white_passed, black_passed],
+        }
     }
 
-    pub fn evaluate(&mut self, position: &mut Position) -> i32 {
-        let material_score = position.material[position.turn as usize]
-            - position.material[(!position.turn) as usize];
+    /// Returns the middlegame and endgame pawn structure scores of the given side, along
+    /// with its passed pawns.
+    fn evaluate_pawns_for(position: &Position, side: Color) -> (i32, i32, u64) {
+        let friendly_pawns = position.bitboards[Piece::WhitePawn as usize + side as usize * 6];
+        let enemy_pawns = position.bitboards[Piece::WhitePawn as usize + (!side) as usize * 6];
+
+        let (front_masks, behind_masks) = if side == Color::White {
+            (
+                &position.white_passed_pawn_masks,
+                &position.black_passed_pawn_masks,
+            )
+        } else {
+            (
+                &position.black_passed_pawn_masks,
+                &position.white_passed_pawn_masks,
+            )
+        };
 
-        return material_score
-            + self.evaluate_pawn_structure(position)
-            + self.evaluate_open_files(position)
-            + self.evaluate_king_safety(position);
-    }
+        let mut mg_score = 0;
+        let mut eg_score = 0;
+        let mut passed_pawns = 0u64;
 
-    pub fn evaluate_pawn_structure(&mut self, position: &mut Position) -> i32 {
-        let mut white_score = 0;
-        let mut black_score = 0;
+        let mut pawns = friendly_pawns;
+        while pawns != 0 {
+            let square = utils::pop_lsb(&mut pawns) as usize;
+            let (rank, stop_square) = if side == Color::White {
+                (GET_RANK[square] as usize, square - 8)
+            } else {
+                (7 - GET_RANK[square] as usize, square + 8)
+            };
 
-        let mut white_pawns = position.bitboards[Piece::WhitePawn as usize];
-        while white_pawns != 0 {
-            let square = utils::pop_lsb(&mut white_pawns);
-            let doubled_pawns = utils::count_bits(
-                position.bitboards[Piece::WhitePawn as usize]
-                    & position.file_masks[square as usize],
-            );
+            let adjacent_files = position.isolated_pawn_masks[square];
+            let file_ahead = front_masks[square] & position.file_masks[square];
+
+            // Friendly pawns on adjacent files that are level with or behind this pawn
+            let helpers = friendly_pawns
+                & adjacent_files
+                & (behind_masks[square] | position.rank_masks[square]);
+
+            let doubled_pawns = utils::count_bits(friendly_pawns & position.file_masks[square]);
             if doubled_pawns > 1 {
-                white_score += DOUBLED_PAWN_PENALTY as i32 * doubled_pawns as i32;
+                mg_score += DOUBLED_PAWN_PENALTY * doubled_pawns as i32;
+                eg_score += DOUBLED_PAWN_PENALTY_EG * doubled_pawns as i32;
             }
 
-            if (position.bitboards[Piece::WhitePawn as usize]
-                & position.isolated_pawn_masks[square as usize])
-                == 0
-            {
-                white_score += ISOLATED_PAWN_PENALTY as i32;
+            let is_isolated = friendly_pawns & adjacent_files == 0;
+            if is_isolated {
+                mg_score += ISOLATED_PAWN_PENALTY;
+                eg_score += ISOLATED_PAWN_PENALTY_EG;
             }
 
-            if (position.white_passed_pawn_masks[square as usize]
-                & position.bitboards[Piece::BlackPawn as usize])
-                == 0
-            {
-                white_score += PASSED_PAWN_BONUS[GET_RANK[square as usize] as usize] as i32
+            let is_passed = front_masks[square] & enemy_pawns == 0;
+            if is_passed {
+                utils::set_bit(&mut passed_pawns, square as u8);
+                mg_score += PASSED_PAWN_BONUS[rank];
+                eg_score += PASSED_PAWN_BONUS_EG[rank];
+            } else if file_ahead & enemy_pawns == 0 {
+                // Candidate passer, enough helpers to force its way past the enemy sentries
+                let sentries = enemy_pawns & adjacent_files & front_masks[square];
+                if helpers.count_ones() >= sentries.count_ones() {
+                    mg_score += CANDIDATE_PASSER_BONUS[rank];
+                    eg_score += CANDIDATE_PASSER_BONUS_EG[rank];
+                }
             }
-        }
 
-        let mut black_pawns = position.bitboards[Piece::BlackPawn as usize];
-        while black_pawns != 0 {
-            let square = utils::pop_lsb(&mut black_pawns);
-            let doubled_pawns = utils::count_bits(
-                position.bitboards[Piece::BlackPawn as usize]
-                    & position.file_masks[square as usize],
-            );
-            if doubled_pawns > 1 {
-                black_score += DOUBLED_PAWN_PENALTY as i32 * doubled_pawns as i32;
+            // Supported by a friendly pawn
+            if position.pawn_attacks[(!side) as usize][square] & friendly_pawns != 0 {
+                mg_score += CONNECTED_PAWN_BONUS[rank];
+                eg_score += CONNECTED_PAWN_BONUS_EG[rank];
             }
 
-            if (position.bitboards[Piece::BlackPawn as usize]
-                & position.isolated_pawn_masks[square as usize])
-                == 0
-            {
-                black_score += ISOLATED_PAWN_PENALTY as i32;
+            // Side by side with a friendly pawn
+            if position.rank_masks[square] & adjacent_files & friendly_pawns != 0 {
+                mg_score += PHALANX_PAWN_BONUS[rank];
+                eg_score += PHALANX_PAWN_BONUS_EG[rank];
             }
 
-            if (position.black_passed_pawn_masks[square as usize]
-                & position.bitboards[Piece::WhitePawn as usize])
-                == 0
+            // Backward, it can't be supported and its stop square is controlled by an enemy pawn
+            if !is_isolated
+                && helpers == 0
+                && position.pawn_attacks[side as usize][stop_square] & enemy_pawns != 0
             {
-                black_score += PASSED_PAWN_BONUS[7 - GET_RANK[square as usize] as usize] as i32
+                mg_score += BACKWARD_PAWN_PENALTY;
+                eg_score += BACKWARD_PAWN_PENALTY_EG;
             }
         }
 
-        return if position.turn == Color::White {
-            white_score - black_score
-        } else {
-            black_score - white_score
-        };
+        (mg_score, eg_score, passed_pawns)
     }
 
     pub fn evaluate_open_files(&mut self, position: &mut Position) -> i32 {
@@ -818,31 +1460,116 @@ impl Evaluator {
         };
     }
 
+    /// Returns the king safety score from the perspective of the side to move. The
+    /// danger from enemy attacks is tapered towards zero as material comes off the board.
     pub fn evaluate_king_safety(&mut self, position: &mut Position) -> i32 {
-        let white_kings = position.bitboards[Piece::WhiteKing as usize];
-        let black_kings = position.bitboards[Piece::BlackKing as usize];
-        let white_pawns = position.bitboards[Piece::WhitePawn as usize];
-        let black_pawns = position.bitboards[Piece::BlackPawn as usize];
-
-        // Use trailing_zeros and count_ones directly (hardware popcnt)
-        let white_score = if white_kings != 0 {
-            let king_sq = white_kings.trailing_zeros() as usize;
-            (position.king_attacks[king_sq] & white_pawns).count_ones() as i32 * 6
-        } else {
-            0
-        };
+        let white_score = self.evaluate_king_safety_for(position, Color::White);
+        let black_score = self.evaluate_king_safety_for(position, Color::Black);
 
-        let black_score = if black_kings != 0 {
-            let king_sq = black_kings.trailing_zeros() as usize;
-            (position.king_attacks[king_sq] & black_pawns).count_ones() as i32 * 6
+        let score = Evaluator::taper(white_score - black_score, 0, Evaluator::get_phase(position));
+
+        if position.turn == Color::White {
+            score
         } else {
-            0
-        };
+            -score
+        }
+    }
+
+    /// Returns the safety of the given side's king, positive values being good for that side.
+    fn evaluate_king_safety_for(&self, position: &Position, side: Color) -> i32 {
+        let side_offset = side as usize * 6;
+        let enemy_offset = (!side) as usize * 6;
+
+        let king = position.bitboards[Piece::WhiteKing as usize + side_offset];
+        if king == 0 {
+            return 0;
+        }
+
+        let king_square = king.trailing_zeros() as usize;
+        let king_file = king_square % 8;
+        let king_rank = GET_RANK[king_square] as i32;
+        let king_zone = position.king_zone_masks[side as usize][king_square];
+        let occupancy = position.occupancies[2];
+
+        let friendly_pawns = position.bitboards[Piece::WhitePawn as usize + side_offset];
+        let enemy_pawns = position.bitboards[Piece::WhitePawn as usize + enemy_offset];
+
+        // Pawn shield
+        let mut score = (position.king_attacks[king_square] & friendly_pawns).count_ones() as i32
+            * KING_SHIELD_BONUS;
+
+        // Open and semi-open files, and pawn storms, on and next to the king's file
+        for file in king_file.saturating_sub(1)..=(king_file + 1).min(7) {
+            let file_mask = position.file_masks[file];
+
+            if friendly_pawns & file_mask == 0 {
+                score -= KING_SEMI_OPEN_FILE_PENALTY;
+
+                if enemy_pawns & file_mask == 0 {
+                    score -= KING_OPEN_FILE_PENALTY;
+                }
+            }
+
+            let mut storming_pawns = enemy_pawns & file_mask;
+            while storming_pawns != 0 {
+                let square = utils::pop_lsb(&mut storming_pawns) as usize;
+                let distance = GET_RANK[square] as i32 - king_rank;
+                let distance = if side == Color::White {
+                    distance
+                } else {
+                    -distance
+                };
+
+                if distance > 0 {
+                    score -= PAWN_STORM_PENALTY[distance as usize];
+                }
+            }
+        }
 
-        // Branchless final calculation
-        let diff = white_score - black_score;
-        let multiplier = 1 - 2 * (position.turn as i32);
-        diff * -multiplier
+        // Attacks on the king zone by enemy pieces
+        let defended = position.get_attacks_by_color(side);
+        let enemy_pieces = position.occupancies[(!side) as usize];
+        let king_sq = Square::from(king_square);
+        let check_squares = [
+            0,
+            position.knight_attacks[king_square],
+            position.get_bishop_magic_attacks(king_sq, occupancy),
+            position.get_rook_magic_attacks(king_sq, occupancy),
+            position.get_queen_magic_attacks(king_sq, occupancy),
+            0,
+        ];
+
+        let mut attackers = 0;
+        let mut attack_units = 0;
+
+        for piece_type in 1..5 {
+            let piece = Piece::from(piece_type + enemy_offset);
+            let mut pieces = position.bitboards[piece as usize];
+
+            while pieces != 0 {
+                let square = Square::from(utils::pop_lsb(&mut pieces));
+                let attacks = position.get_piece_attacks(piece, square, occupancy);
+
+                let zone_attacks = attacks & king_zone;
+                if zone_attacks != 0 {
+                    attackers += 1;
+                    attack_units +=
+                        zone_attacks.count_ones() as i32 * KING_ATTACK_WEIGHTS[piece_type];
+                }
+
+                let safe_checks = attacks & check_squares[piece_type] & !defended & !enemy_pieces;
+                if safe_checks != 0 {
+                    attack_units += SAFE_CHECK_WEIGHTS[piece_type];
+                }
+            }
+        }
+
+        // A lone attacker is rarely dangerous
+        if attackers >= 2 {
+            score -= KING_DANGER_TABLE[(attack_units as usize).min(KING_DANGER_TABLE.len() - 1)];
+        }
+
+        score
     }
 }
 
@@ -852,6 +1579,9 @@ mod tests {
     use crate::board::Board;
     use crate::chess::constants::STARTING_FEN;
     use crate::chess::square::Square;
+    use crate::movegen::MoveGenerator;
+    use crate::search::listener::ChannelListener;
+    use crate::search::utils::parse_move;
 
     #[test]
     fn short_movetime_returns_legal_move() {
@@ -878,4 +1608,299 @@ mod tests {
         let is_legal = position.make_move(m, false);
         assert!(is_legal, "Expected a legal move, but move was illegal");
     }
+
+    #[test]
+    fn root_moves_keep_the_best_move_first() {
+        let mut position = Position::new(Some(
+            "r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - 0 1",
+        ));
+        let mut evaluator = Evaluator::new();
+        evaluator.set_silent(true);
+        let mut tt = TranspositionTable::new(32);
+        let stop_flag = Arc::new(AtomicBool::new(false));
+
+        let mut options = SearchOptions::new();
+        options.depth = Some(6);
+        let best_move = evaluator.get_best_move(&mut position, options, &mut tt, &stop_flag);
+
+        assert!(evaluator.result.seldepth >= 6);
+
+        let root_moves = evaluator.get_root_moves();
+        assert_eq!(root_moves.len(), position.generate_legal_moves().len());
+        assert_eq!(Some(root_moves[0].m), best_move);
+        assert!(root_moves[1..]
+            .windows(2)
+            .all(|pair| pair[0].nodes >= pair[1].nodes));
+    }
+
+    #[test]
+    fn search_without_legal_moves_has_no_best_move() {
+        // Stalemate
+        let mut position = Position::new(Some("7k/8/5KQ1/8/8/8/8/8 b - - 0 1"));
+        let mut evaluator = Evaluator::new();
+        evaluator.set_silent(true);
+        let mut tt = TranspositionTable::new(32);
+        let stop_flag = Arc::new(AtomicBool::new(false));
+
+        let mut options = SearchOptions::new();
+        options.depth = Some(3);
+        let best_move = evaluator.get_best_move(&mut position, options, &mut tt, &stop_flag);
+
+        assert_eq!(best_move, None);
+    }
+
+    #[test]
+    fn listener_receives_the_search_reports() {
+        let mut position = Position::new(Some(STARTING_FEN));
+        let mut evaluator = Evaluator::new();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        evaluator.set_listener(Box::new(ChannelListener::new(sender)));
+        let mut tt = TranspositionTable::new(32);
+        let stop_flag = Arc::new(AtomicBool::new(false));
+
+        let mut options = SearchOptions::new();
+        options.depth = Some(4);
+        let best_move = evaluator.get_best_move(&mut position, options, &mut tt, &stop_flag);
+
+        let reports: Vec<SearchInfo> = receiver.try_iter().collect();
+        let depths: Vec<u8> = reports
+            .iter()
+            .filter_map(|info| match info {
+                SearchInfo::Iteration(iteration) if iteration.bound == Bound::Exact => {
+                    Some(iteration.depth)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(depths, vec![1, 2, 3, 4]);
+        assert!(reports
+            .iter()
+            .any(|info| matches!(info, SearchInfo::NewBestMove { .. })));
+        assert_eq!(reports.last(), Some(&SearchInfo::BestMove(best_move)));
+
+        // Every reported line, bounds included, starts with the best move found so far
+        let mut latest_best_move = None;
+        for info in &reports {
+            match info {
+                SearchInfo::NewBestMove { m, .. } => latest_best_move = Some(*m),
+                SearchInfo::Iteration(iteration) => {
+                    assert_eq!(iteration.pv.first().copied(), latest_best_move)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn reductions_grow_with_depth_and_move_number() {
+        let evaluator = Evaluator::new();
+
+        assert_eq!(evaluator.get_reduction(1, 1), 0);
+        assert!(evaluator.get_reduction(3, 4) <= evaluator.get_reduction(3, 20));
+        assert!(evaluator.get_reduction(3, 20) <= evaluator.get_reduction(20, 20));
+        assert!(evaluator.get_reduction(20, 40) > 1);
+
+        // Depths and move numbers beyond the table use its last entries
+        assert_eq!(
+            evaluator.get_reduction(255, 255),
+            evaluator.reductions[MAX_PLY - 1][63]
+        );
+    }
+
+    #[test]
+    fn pruning_keeps_quiet_mates() {
+        // Black's king is boxed in behind its own pawns, white mates on the back rank
+        let mut position = Position::new(Some("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1"));
+        let mut evaluator = Evaluator::new();
+        evaluator.set_silent(true);
+        let mut tt = TranspositionTable::new(32);
+        let stop_flag = Arc::new(AtomicBool::new(false));
+
+        let mut options = SearchOptions::new();
+        options.depth = Some(6);
+        let best_move = evaluator.get_best_move(&mut position, options, &mut tt, &stop_flag);
+
+        assert_eq!(best_move.unwrap().to_string(), "d1d8");
+        assert_eq!(Evaluator::get_mate_in(evaluator.result.score), Some(1));
+    }
+
+    #[test]
+    fn wdl_follows_the_score() {
+        let (win, draw, loss) = Evaluator::get_wdl(0);
+        assert_eq!(win, loss);
+        assert_eq!(win + draw + loss, 1000);
+
+        let (win, _, loss) = Evaluator::get_wdl(WDL_MIDPOINT);
+        assert_eq!(win, 500);
+        assert!(loss < 5);
+
+        let (small_edge, _, _) = Evaluator::get_wdl(50);
+        let (large_edge, _, _) = Evaluator::get_wdl(400);
+        assert!(small_edge < large_edge);
+
+        assert_eq!(Evaluator::get_wdl(MATE - 3), (1000, 0, 0));
+        assert_eq!(Evaluator::get_wdl(-MATE + 2), (0, 0, 1000));
+    }
+
+    #[test]
+    fn tt_move_comes_from_any_bound() {
+        let mut position = Position::new(Some(STARTING_FEN));
+        let evaluator = Evaluator::new();
+        let mut tt = TranspositionTable::new(32);
+        assert_eq!(
+            evaluator.get_tt_move(&position, &tt),
+            BitPackedMove::default()
+        );
+
+        // A move that failed high is still the best guess for this position
+        let m = parse_move(&mut position, "e2e4").unwrap();
+        tt.save(
+            position.hash,
+            5,
+            tt::TranspositionTableEntryFlag::BETA,
+            120,
+            0,
+            m,
+        );
+        assert_eq!(evaluator.get_tt_move(&position, &tt), m);
+    }
+
+    #[test]
+    fn mate_scores_count_moves_to_mate() {
+        assert_eq!(Evaluator::get_mate_in(MATE - 1), Some(1));
+        assert_eq!(Evaluator::get_mate_in(MATE - 4), Some(2));
+        assert_eq!(Evaluator::get_mate_in(-MATE + 2), Some(-1));
+        assert_eq!(Evaluator::get_mate_in(-MATE + 6), Some(-3));
+        assert_eq!(Evaluator::get_mate_in(350), None);
+        assert_eq!(Evaluator::get_mate_in(-MATE_IN_MAX_PLY + 1), None);
+    }
+
+    #[test]
+    fn quiescence_finds_checking_mates() {
+        let mut position = Position::new(Some("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1"));
+        let mut evaluator = Evaluator::new();
+        let mut tt = TranspositionTable::new(32);
+        evaluator.running = true;
+
+        // The quiet check is only tried on the first quiescence ply, where it mates
+        let score = evaluator.quiescence(&mut position, -MATE, MATE, true, &mut tt);
+        assert_eq!(score, MATE - 1);
+
+        tt.clear();
+        let score = evaluator.quiescence(&mut position, -MATE, MATE, false, &mut tt);
+        assert!(score < MATE_IN_MAX_PLY);
+
+        // Once mated there is no standing pat
+        let mate = parse_move(&mut position, "d1d8").unwrap();
+        position.make_move(mate, false);
+        let score = evaluator.quiescence(&mut position, -MATE, MATE, false, &mut tt);
+        assert_eq!(score, -MATE);
+    }
+
+    #[test]
+    fn exclusion_search_skips_the_move_and_the_table() {
+        let mut position = Position::new(Some("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1"));
+        let mut evaluator = Evaluator::new();
+        let mut tt = TranspositionTable::new(32);
+        evaluator.running = true;
+
+        // Without the mating move white is no longer winning
+        let mate = parse_move(&mut position, "d1d8").unwrap();
+        evaluator.stack[0].excluded_move = mate;
+        let score = evaluator.negamax(
+            &mut position,
+            -INFINITY,
+            INFINITY,
+            3,
+            NodeType::NonPV,
+            &mut tt,
+        );
+
+        assert!(score < MATE_IN_MAX_PLY);
+        assert!(tt.probe(position.hash).is_none());
+    }
+
+    #[test]
+    fn detects_pawn_only_positions() {
+        let evaluator = Evaluator::new();
+
+        let mut position = Position::new(Some("4k3/3p4/8/8/8/8/3P4/4K3 w - - 0 1"));
+        assert!(!evaluator.has_non_pawn_material(&mut position));
+
+        // Only the side to move counts
+        let mut position = Position::new(Some("4k3/3p4/8/8/8/8/3P4/3NK3 b - - 0 1"));
+        assert!(!evaluator.has_non_pawn_material(&mut position));
+        position.turn = Color::White;
+        assert!(evaluator.has_non_pawn_material(&mut position));
+    }
+
+    #[test]
+    fn king_safety_penalizes_exposed_king() {
+        let mut evaluator = Evaluator::new();
+
+        let mut position = Position::new(Some(STARTING_FEN));
+        assert_eq!(evaluator.evaluate_king_safety(&mut position), 0);
+
+        // White's king has no shield and is attacked by the queen, rook and bishop
+        let mut position =
+            Position::new(Some("r1b2rk1/pp3ppp/2n5/8/3b4/5q1r/8/R1BQ1RK1 w - - 0 1"));
+        let white_to_move = evaluator.evaluate_king_safety(&mut position);
+        assert!(
+            white_to_move < 0,
+            "expected a penalty, got {}",
+            white_to_move
+        );
+
+        position.turn = Color::Black;
+        assert_eq!(
+            evaluator.evaluate_king_safety(&mut position),
+            -white_to_move
+        );
+    }
+
+    #[test]
+    fn pawn_structure_is_cached() {
+        // White has a protected passed pawn, black has doubled, isolated pawns
+        let fen = "4k3/8/2p5/2p1P3/3P4/8/8/4K3 w - - 0 1";
+        let mut evaluator = Evaluator::new();
+        let mut position = Position::new(Some(fen));
+        assert!(evaluator.pawn_table.probe(position.pawn_hash).is_none());
+
+        let uncached = evaluator.evaluate_pawn_structure(&mut position);
+        let entry = evaluator.pawn_table.probe(position.pawn_hash).unwrap();
+        let expected = Evaluator::evaluate_pawns(&position);
+        assert_eq!(entry.key, position.pawn_hash);
+        assert_eq!(
+            (entry.mg_score, entry.eg_score),
+            (expected.mg_score, expected.eg_score)
+        );
+        assert_eq!(entry.passed_pawns, expected.passed_pawns);
+        assert_eq!(
+            entry.passed_pawns[Color::White as usize],
+            1 << Square::E5 as u64
+        );
+
+        let cached = evaluator.evaluate_pawn_structure(&mut position);
+        assert_eq!(uncached, cached);
+
+        // The next evaluation reads the table rather than the pawns
+        evaluator.pawn_table.store(PawnHashEntry {
+            mg_score: entry.mg_score + 100,
+            eg_score: entry.eg_score + 100,
+            ..entry
+        });
+        assert_eq!(
+            evaluator.evaluate_pawn_structure(&mut position),
+            uncached + 100
+        );
+        evaluator.pawn_table.store(entry);
+        assert!(
+            uncached > 0,
+            "expected white to be better, got {}",
+            uncached
+        );
+
+        position.turn = Color::Black;
+        assert_eq!(evaluator.evaluate_pawn_structure(&mut position), -uncached);
+    }
 }
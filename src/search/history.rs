@@ -0,0 +1,177 @@
+use crate::{
+    chess::{_move::BitPackedMove, color::Color},
+    search::constants::{HISTORY_BONUS_SCALE, MAX_HISTORY, MAX_HISTORY_BONUS},
+};
+
+/*
+   History
+   -------
+   Remembers how moves fared in earlier parts of the search, to order and prune moves that have
+   not been searched yet in the current position:
+
+   - Butterfly history, by side to move and the from and to squares of quiet moves
+   - Continuation history, by the piece and to square of the move played one and two plies
+     earlier and the piece and to square of quiet moves, which catches follow-ups to moves
+   - Capture history, by the moving piece, the to square and the captured piece type
+
+   Moves that cause a beta cutoff get a bonus, the moves of the same kind searched before them
+   get a malus of the same size. Updates use gravity: the closer an entry is to MAX_HISTORY,
+   the less a bonus moves it, so entries never overflow and old results fade over time.
+*/
+pub struct History {
+    butterfly: Vec<i16>,
+    /// Continuation histories by the number of plies back the previous move was played, minus one
+    continuation: [Vec<i16>; 2],
+    capture: Vec<i16>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            butterfly: vec![0; 2 * 64 * 64],
+            continuation: [vec![0; 12 * 64 * 12 * 64], vec![0; 12 * 64 * 12 * 64]],
+            capture: vec![0; 12 * 64 * 6],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.butterfly.fill(0);
+        self.continuation[0].fill(0);
+        self.continuation[1].fill(0);
+        self.capture.fill(0);
+    }
+
+    /// Returns the bonus for a move that caused a beta cutoff at the given depth.
+    pub fn get_bonus(depth: u8) -> i32 {
+        (HISTORY_BONUS_SCALE * depth as i32 * depth as i32).min(MAX_HISTORY_BONUS)
+    }
+
+    /// Returns the history score of a quiet move, given the moves played one and two plies
+    /// earlier, null moves when there were none.
+    pub fn get_quiet_score(
+        &self,
+        side: Color,
+        m: BitPackedMove,
+        previous: [BitPackedMove; 2],
+    ) -> i32 {
+        let mut score = self.butterfly[History::butterfly_index(side, m)] as i32;
+        for (plies_back, prev) in previous.iter().enumerate() {
+            if let Some(index) = History::continuation_index(*prev, m) {
+                score += self.continuation[plies_back][index] as i32;
+            }
+        }
+        score
+    }
+
+    pub fn get_capture_score(&self, m: BitPackedMove) -> i32 {
+        self.capture[History::capture_index(m)] as i32
+    }
+
+    /// Rewards the quiet move that caused a cutoff and penalizes the quiet moves searched
+    /// before it.
+    pub fn update_quiets(
+        &mut self,
+        side: Color,
+        best: BitPackedMove,
+        searched: &[BitPackedMove],
+        previous: [BitPackedMove; 2],
+        depth: u8,
+    ) {
+        let bonus = History::get_bonus(depth);
+        for &m in searched {
+            let bonus = if m == best { bonus } else { -bonus };
+
+            History::apply_bonus(
+                &mut self.butterfly[History::butterfly_index(side, m)],
+                bonus,
+            );
+            for (plies_back, prev) in previous.iter().enumerate() {
+                if let Some(index) = History::continuation_index(*prev, m) {
+                    History::apply_bonus(&mut self.continuation[plies_back][index], bonus);
+                }
+            }
+        }
+    }
+
+    /// Rewards the capture that caused a cutoff, if it was one, and penalizes the other
+    /// captures searched.
+    pub fn update_captures(&mut self, best: BitPackedMove, searched: &[BitPackedMove], depth: u8) {
+        let bonus = History::get_bonus(depth);
+        for &m in searched {
+            let bonus = if m == best { bonus } else { -bonus };
+            History::apply_bonus(&mut self.capture[History::capture_index(m)], bonus);
+        }
+    }
+
+    fn apply_bonus(entry: &mut i16, bonus: i32) {
+        let value = *entry as i32;
+        *entry = (value + bonus - value * bonus.abs() / MAX_HISTORY) as i16;
+    }
+
+    fn butterfly_index(side: Color, m: BitPackedMove) -> usize {
+        (side as usize * 64 + m.get_from() as usize) * 64 + m.get_to() as usize
+    }
+
+    fn continuation_index(prev: BitPackedMove, m: BitPackedMove) -> Option<usize> {
+        if prev == BitPackedMove::default() {
+            return None;
+        }
+        let prev_index = prev.get_piece() as usize * 64 + prev.get_to() as usize;
+        Some((prev_index * 12 + m.get_piece() as usize) * 64 + m.get_to() as usize)
+    }
+
+    fn capture_index(m: BitPackedMove) -> usize {
+        // En passant moves don't record a captured piece, but always take a pawn
+        let captured = if m.is_enpassant() {
+            0
+        } else {
+            m.get_capture() as usize % 6
+        };
+        (m.get_piece() as usize * 64 + m.get_to() as usize) * 6 + captured
+    }
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::History;
+    use crate::{
+        chess::{_move::BitPackedMove, color::Color, piece::Piece, square::Square},
+        search::constants::MAX_HISTORY,
+    };
+
+    #[test]
+    fn cutoffs_reward_the_best_move_and_saturate() {
+        let mut history = History::new();
+        let best = BitPackedMove::new(Square::G1, Square::F3, Piece::WhiteKnight);
+        let other = BitPackedMove::new(Square::A2, Square::A3, Piece::WhitePawn);
+        let previous = [
+            BitPackedMove::new(Square::E7, Square::E5, Piece::BlackPawn),
+            BitPackedMove::default(),
+        ];
+
+        history.update_quiets(Color::White, best, &[other, best], previous, 4);
+        assert!(history.get_quiet_score(Color::White, best, previous) > 0);
+        assert!(history.get_quiet_score(Color::White, other, previous) < 0);
+        assert_eq!(
+            history.get_quiet_score(Color::Black, best, [BitPackedMove::default(); 2]),
+            0
+        );
+
+        // The follow-up bonus only applies after the same previous move
+        let butterfly_only =
+            history.get_quiet_score(Color::White, best, [BitPackedMove::default(); 2]);
+        assert!(butterfly_only < history.get_quiet_score(Color::White, best, previous));
+
+        for _ in 0..1000 {
+            history.update_quiets(Color::White, best, &[best], previous, 20);
+        }
+        let score = history.get_quiet_score(Color::White, best, [BitPackedMove::default(); 2]);
+        assert!(score > 0 && score <= MAX_HISTORY);
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    io::{self, Write},
+    sync::mpsc::Sender,
+};
+
+use crate::chess::_move::BitPackedMove;
+
+/// What a score tells about the position, the score of a search that failed high or low of its
+/// aspiration window being a bound
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+/// The state of the search at the end of an iteration, or of a search of it that failed high
+/// or low. Nodes and time count from the start of the search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IterationInfo {
+    pub depth: u8,
+    pub seldepth: u32,
+    pub score: i32,
+    /// Moves until mate for mate scores, negative when getting mated
+    pub mate_in: Option<i32>,
+    pub bound: Bound,
+    /// Expected wins, draws and losses in permille, when asked for
+    pub wdl: Option<(i32, i32, i32)>,
+    pub nodes: u64,
+    pub nps: u64,
+    /// Tablebase hits, always 0 until the engine probes tablebases
+    pub tbhits: u64,
+    pub time: u128,
+    pub hashfull: u32,
+    pub pv: Vec<BitPackedMove>,
+}
+
+/// Something that happened during a search
+#[derive(Clone, Debug, PartialEq)]
+pub enum SearchInfo {
+    /// An iteration completed, or failed high or low of its aspiration window
+    Iteration(IterationInfo),
+    /// A root move became the best move of the iteration being searched
+    NewBestMove {
+        depth: u8,
+        m: BitPackedMove,
+        score: i32,
+    },
+    /// The root move being searched, only reported once the search has run for a while
+    CurrentMove {
+        depth: u8,
+        m: BitPackedMove,
+        number: usize,
+    },
+    /// The search finished, with no move when the position has no legal moves
+    BestMove(Option<BitPackedMove>),
+    /// A diagnostic message
+    Message(String),
+}
+
+/// Receives what happens during a search, so it can be shown or acted upon
+pub trait SearchListener: Send {
+    fn on_info(&mut self, info: SearchInfo);
+}
+
+/// Writes search information to stdout as UCI `info` and `bestmove` lines
+pub struct UciListener;
+
+impl UciListener {
+    /// Returns the UCI line for an event, None for events UCI has no line for.
+    pub fn format(info: &SearchInfo) -> Option<String> {
+        match info {
+            SearchInfo::Iteration(iteration) => {
+                let score = match iteration.mate_in {
+                    Some(mate_in) => format!("mate {}", mate_in),
+                    None => format!("cp {}", iteration.score),
+                };
+                let bound = match iteration.bound {
+                    Bound::Exact => "",
+                    Bound::Lower => " lowerbound",
+                    Bound::Upper => " upperbound",
+                };
+                let wdl = match iteration.wdl {
+                    Some((win, draw, loss)) => format!(" wdl {} {} {}", win, draw, loss),
+                    None => String::new(),
+                };
+                let mut pv = String::new();
+                for m in &iteration.pv {
+                    pv.push(' ');
+                    pv.push_str(&m.to_string());
+                }
+
+                Some(format!(
+                    "info score {}{}{} depth {} seldepth {} nodes {} nps {} tbhits {} time {} hashfull {} pv{}",
+                    score,
+                    bound,
+                    wdl,
+                    iteration.depth,
+                    iteration.seldepth,
+                    iteration.nodes,
+                    iteration.nps,
+                    iteration.tbhits,
+                    iteration.time,
+                    iteration.hashfull,
+                    pv
+                ))
+            }
+            SearchInfo::NewBestMove { .. } => None,
+            SearchInfo::CurrentMove { depth, m, number } => Some(format!(
+                "info depth {} currmove {} currmovenumber {}",
+                depth, m, number
+            )),
+            SearchInfo::BestMove(Some(m)) => Some(format!("bestmove {}", m)),
+            SearchInfo::BestMove(None) => Some(String::from("bestmove 0000")),
+            SearchInfo::Message(message) => Some(format!("info string {}", message)),
+        }
+    }
+}
+
+impl SearchListener for UciListener {
+    fn on_info(&mut self, info: SearchInfo) {
+        if let Some(line) = UciListener::format(&info) {
+            println!("{}", line);
+            io::stdout().flush().unwrap();
+        }
+    }
+}
+
+/// Ignores everything, for searches nobody is watching
+pub struct SilentListener;
+
+impl SearchListener for SilentListener {
+    fn on_info(&mut self, _info: SearchInfo) {}
+}
+
+/// Sends every event down a channel, for embedding the search in another program
+pub struct ChannelListener {
+    sender: Sender<SearchInfo>,
+}
+
+impl ChannelListener {
+    pub fn new(sender: Sender<SearchInfo>) -> ChannelListener {
+        ChannelListener { sender }
+    }
+}
+
+impl SearchListener for ChannelListener {
+    fn on_info(&mut self, info: SearchInfo) {
+        // A receiver that hung up has lost interest, the search carries on regardless
+        let _ = self.sender.send(info);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bound, IterationInfo, SearchInfo, UciListener};
+    use crate::chess::{_move::BitPackedMove, piece::Piece, square::Square};
+
+    #[test]
+    fn formats_uci_lines() {
+        let e4 = BitPackedMove::new(Square::E2, Square::E4, Piece::WhitePawn);
+        let e5 = BitPackedMove::new(Square::E7, Square::E5, Piece::BlackPawn);
+        let iteration = IterationInfo {
+            depth: 7,
+            seldepth: 12,
+            score: 35,
+            mate_in: None,
+            bound: Bound::Lower,
+            wdl: Some((120, 850, 30)),
+            nodes: 5000,
+            nps: 100000,
+            tbhits: 0,
+            time: 50,
+            hashfull: 3,
+            pv: vec![e4, e5],
+        };
+
+        assert_eq!(
+            UciListener::format(&SearchInfo::Iteration(iteration)).unwrap(),
+            "info score cp 35 lowerbound wdl 120 850 30 depth 7 seldepth 12 nodes 5000 \
+             nps 100000 tbhits 0 time 50 hashfull 3 pv e2e4 e7e5"
+        );
+        assert_eq!(
+            UciListener::format(&SearchInfo::CurrentMove {
+                depth: 9,
+                m: e4,
+                number: 1
+            })
+            .unwrap(),
+            "info depth 9 currmove e2e4 currmovenumber 1"
+        );
+        assert_eq!(
+            UciListener::format(&SearchInfo::BestMove(None)).unwrap(),
+            "bestmove 0000"
+        );
+        assert!(UciListener::format(&SearchInfo::NewBestMove {
+            depth: 9,
+            m: e4,
+            score: 20
+        })
+        .is_none());
+    }
+}
@@ -0,0 +1,316 @@
+use crate::{
+    board::Position,
+    chess::{
+        _move::BitPackedMove,
+        move_list::{MoveList, MAX_MOVES},
+        piece::Piece,
+    },
+    movegen::{LegalityMasks, MoveGenerator},
+    search::constants::_MVV_LVA,
+};
+
+/*
+   MovePicker
+   ----------
+   Hands out the moves of a position one at a time, roughly best first, generating them in
+   stages so that nodes which cut off early never pay for generating and sorting every move:
+
+   1. The transposition table move, verified as pseudo-legal, without generating anything
+   2. Captures that win or break even according to static exchange evaluation, by MVV-LVA and
+      then by their capture history
+   3. The two killer moves and the counter move, verified as pseudo-legal
+   4. The remaining quiet moves, by their history score
+   5. The captures that lose material
+
+   Quiescence pickers never hand out the losing captures, and only hand out quiet moves when
+   asked to, for the search to try the ones that give check. Only legal moves are handed out, so
+   they can be made with `Position::make_legal_move`.
+*/
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Stage {
+    TTMove,
+    GenerateCaptures,
+    GoodCaptures,
+    FirstKiller,
+    SecondKiller,
+    CounterMove,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+pub struct MovePicker {
+    stage: Stage,
+    tt_move: BitPackedMove,
+    killers: [BitPackedMove; 2],
+    counter_move: BitPackedMove,
+    skip_quiets: bool,
+    skip_bad_captures: bool,
+    masks: LegalityMasks,
+
+    moves: MoveList,
+    scores: [i32; MAX_MOVES],
+    index: usize,
+
+    bad_captures: MoveList,
+    bad_capture_index: usize,
+}
+
+impl MovePicker {
+    pub fn new(
+        position: &Position,
+        tt_move: BitPackedMove,
+        killers: [BitPackedMove; 2],
+        counter_move: BitPackedMove,
+    ) -> MovePicker {
+        MovePicker {
+            stage: Stage::TTMove,
+            tt_move,
+            killers,
+            counter_move,
+            skip_quiets: false,
+            skip_bad_captures: false,
+            masks: position.get_legality_masks(),
+            moves: MoveList::new(),
+            scores: [0; MAX_MOVES],
+            index: 0,
+            bad_captures: MoveList::new(),
+            bad_capture_index: 0,
+        }
+    }
+
+    /// Returns a picker that only hands out the transposition table move and winning captures,
+    /// followed by the quiet moves when `with_quiets` is set
+    pub fn new_quiescence(
+        position: &Position,
+        tt_move: BitPackedMove,
+        with_quiets: bool,
+    ) -> MovePicker {
+        let mut picker = MovePicker::new(
+            position,
+            tt_move,
+            [BitPackedMove::default(); 2],
+            BitPackedMove::default(),
+        );
+        picker.skip_quiets = !with_quiets;
+        picker.skip_bad_captures = true;
+        picker
+    }
+
+    /// Returns the next move to search, or None once every move has been handed out. The
+    /// given function returns the history score of a move, higher scores being searched first.
+    pub fn next<F: Fn(BitPackedMove) -> i32>(
+        &mut self,
+        position: &mut Position,
+        history_score: F,
+    ) -> Option<BitPackedMove> {
+        loop {
+            match self.stage {
+                Stage::TTMove => {
+                    self.stage = Stage::GenerateCaptures;
+
+                    if self.tt_move != BitPackedMove::default()
+                        && (!self.skip_quiets || MovePicker::is_tactical(self.tt_move))
+                        && position.is_pseudo_legal(self.tt_move)
+                        && position.is_legal(self.tt_move, &self.masks)
+                    {
+                        return Some(self.tt_move);
+                    }
+                }
+
+                Stage::GenerateCaptures => {
+                    self.moves.clear();
+                    for m in position.generate_moves(true) {
+                        if position.is_legal(m, &self.masks) {
+                            // Victims are 1600 apart and attackers 16, so capture history
+                            // orders captures of the same victim, and at its extremes can
+                            // swap victims of neighbouring value
+                            self.scores[self.moves.len()] =
+                                MovePicker::mvv_lva(m) * 16 + history_score(m) / 16;
+                            self.moves.push(m);
+                        }
+                    }
+                    self.index = 0;
+                    self.stage = Stage::GoodCaptures;
+                }
+
+                Stage::GoodCaptures => match self.select_best() {
+                    Some(m) => {
+                        if m == self.tt_move {
+                            continue;
+                        }
+                        if !position.see_ge(m, 0) {
+                            self.bad_captures.push(m);
+                            continue;
+                        }
+                        return Some(m);
+                    }
+                    None => {
+                        self.stage = if self.skip_quiets {
+                            Stage::Done
+                        } else {
+                            Stage::FirstKiller
+                        };
+                    }
+                },
+
+                Stage::FirstKiller | Stage::SecondKiller | Stage::CounterMove => {
+                    let m = match self.stage {
+                        Stage::FirstKiller => self.killers[0],
+                        Stage::SecondKiller => self.killers[1],
+                        _ => self.counter_move,
+                    };
+                    let is_duplicate = match self.stage {
+                        Stage::FirstKiller => false,
+                        Stage::SecondKiller => m == self.killers[0],
+                        _ => m == self.killers[0] || m == self.killers[1],
+                    };
+
+                    self.stage = match self.stage {
+                        Stage::FirstKiller => Stage::SecondKiller,
+                        Stage::SecondKiller => Stage::CounterMove,
+                        _ => Stage::GenerateQuiets,
+                    };
+
+                    if m != BitPackedMove::default()
+                        && m != self.tt_move
+                        && !is_duplicate
+                        && !MovePicker::is_tactical(m)
+                        && position.is_pseudo_legal(m)
+                        && position.is_legal(m, &self.masks)
+                    {
+                        return Some(m);
+                    }
+                }
+
+                Stage::GenerateQuiets => {
+                    self.moves.clear();
+                    for m in position.generate_moves(false) {
+                        if MovePicker::is_tactical(m)
+                            || self.was_picked_early(m)
+                            || !position.is_legal(m, &self.masks)
+                        {
+                            continue;
+                        }
+
+                        // Promotions are searched before any of the other quiet moves
+                        self.scores[self.moves.len()] = if m.is_promotion() {
+                            i32::MAX
+                        } else {
+                            history_score(m)
+                        };
+                        self.moves.push(m);
+                    }
+                    self.index = 0;
+                    self.stage = Stage::Quiets;
+                }
+
+                Stage::Quiets => match self.select_best() {
+                    Some(m) => return Some(m),
+                    None => {
+                        self.stage = if self.skip_bad_captures {
+                            Stage::Done
+                        } else {
+                            Stage::BadCaptures
+                        };
+                    }
+                },
+
+                Stage::BadCaptures => {
+                    if self.bad_capture_index < self.bad_captures.len() {
+                        self.bad_capture_index += 1;
+                        return Some(self.bad_captures[self.bad_capture_index - 1]);
+                    }
+                    self.stage = Stage::Done;
+                }
+
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    /// Moves the highest scoring remaining move to the front of the unpicked moves and
+    /// returns it.
+    fn select_best(&mut self) -> Option<BitPackedMove> {
+        if self.index >= self.moves.len() {
+            return None;
+        }
+
+        let mut best = self.index;
+        for i in (self.index + 1)..self.moves.len() {
+            if self.scores[i] > self.scores[best] {
+                best = i;
+            }
+        }
+
+        self.moves.swap(self.index, best);
+        self.scores.swap(self.index, best);
+        self.index += 1;
+
+        Some(self.moves[self.index - 1])
+    }
+
+    /// Returns true if the move was already handed out before the quiet moves were generated
+    fn was_picked_early(&self, m: BitPackedMove) -> bool {
+        m == self.tt_move || m == self.killers[0] || m == self.killers[1] || m == self.counter_move
+    }
+
+    /// Returns true for moves that are generated with the captures
+    fn is_tactical(m: BitPackedMove) -> bool {
+        m.is_capture() || m.is_enpassant()
+    }
+
+    fn mvv_lva(m: BitPackedMove) -> i32 {
+        let captured = if m.is_enpassant() {
+            Piece::WhitePawn
+        } else {
+            m.get_capture()
+        };
+        _MVV_LVA[m.get_piece() as usize][captured as usize] as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MovePicker;
+    use crate::{
+        board::{Board, Position},
+        chess::_move::BitPackedMove,
+        movegen::MoveGenerator,
+        search::utils::parse_move,
+    };
+
+    #[test]
+    fn picks_every_move_once_with_tt_move_first() {
+        let mut position = Position::new(Some(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        ));
+        let tt_move = parse_move(&mut position, "e2a6").unwrap();
+        let killer = parse_move(&mut position, "a2a3").unwrap();
+
+        // A killer from another position that is not pseudo-legal here
+        let mut other = Position::new(Some(crate::chess::constants::STARTING_FEN));
+        let bogus_killer = parse_move(&mut other, "g1h3").unwrap();
+
+        let mut picker = MovePicker::new(
+            &position,
+            tt_move,
+            [killer, bogus_killer],
+            BitPackedMove::default(),
+        );
+        let mut picked = Vec::new();
+        while let Some(m) = picker.next(&mut position, |_| 0) {
+            picked.push(m);
+        }
+
+        let mut generated: Vec<BitPackedMove> =
+            position.generate_legal_moves().into_iter().collect();
+        assert_eq!(picked[0], tt_move);
+        assert_eq!(picked.len(), generated.len());
+
+        generated.sort_by_key(|m| m.move_bits);
+        picked.sort_by_key(|m| m.move_bits);
+        assert_eq!(picked, generated);
+    }
+}
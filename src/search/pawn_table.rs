@@ -0,0 +1,70 @@
+/*
+   PawnHashTable
+   -------------
+   Pawn structure changes far less often than the rest of the position, so its evaluation is
+   cached in a small hash table indexed by the pawn-only zobrist key (`Position::pawn_hash`).
+   Entries hold the white-relative middlegame and endgame pawn scores along with the passed
+   pawns of each side, which are needed by evaluation terms that also depend on pieces.
+*/
+pub struct PawnHashTable {
+    table: Vec<PawnHashEntry>,
+    size: usize,
+}
+
+#[derive(Clone, Copy)]
+pub struct PawnHashEntry {
+    pub key: u64,
+    pub mg_score: i32,
+    pub eg_score: i32,
+    /// Passed pawns, indexed by color
+    pub passed_pawns: [u64; 2],
+}
+
+impl PawnHashEntry {
+    pub fn new() -> PawnHashEntry {
+        PawnHashEntry {
+            key: 0,
+            mg_score: 0,
+            eg_score: 0,
+            passed_pawns: [0; 2],
+        }
+    }
+}
+
+impl Default for PawnHashEntry {
+    fn default() -> PawnHashEntry {
+        PawnHashEntry::new()
+    }
+}
+
+impl PawnHashTable {
+    pub fn new(hash_size_in_mb: usize) -> PawnHashTable {
+        let entries = hash_size_in_mb * 1024 * 1024 / std::mem::size_of::<PawnHashEntry>();
+
+        // Round down to a power of two so the key can be masked into an index
+        let size = 1 << (usize::BITS - 1 - entries.max(1).leading_zeros());
+
+        PawnHashTable {
+            table: vec![PawnHashEntry::new(); size],
+            size,
+        }
+    }
+
+    /// Returns the cached entry for the given pawn key, if there is one.
+    pub fn probe(&self, key: u64) -> Option<PawnHashEntry> {
+        let entry = self.table[key as usize & (self.size - 1)];
+        if entry.key == key {
+            return Some(entry);
+        }
+        None
+    }
+
+    /// Stores an entry, always replacing whatever was in its slot.
+    pub fn store(&mut self, entry: PawnHashEntry) {
+        self.table[entry.key as usize & (self.size - 1)] = entry;
+    }
+
+    pub fn clear(&mut self) {
+        self.table.fill(PawnHashEntry::new());
+    }
+}
@@ -0,0 +1,133 @@
+use std::ops::{Index, IndexMut};
+
+use crate::{
+    chess::_move::BitPackedMove,
+    search::constants::{MAX_PLY, NO_STATIC_EVAL},
+};
+
+/// What the search keeps about the node at one ply of the current search path
+#[derive(Clone, Copy, Debug)]
+pub struct SearchStackEntry {
+    /// The move being searched from this node, the null move during a null move search. With
+    /// the move of the ply before, it selects the continuation histories of the node below.
+    pub current_move: BitPackedMove,
+    /// The corrected static evaluation, NO_STATIC_EVAL when in check
+    pub static_eval: i32,
+    /// Quiet moves that caused a cutoff at this ply in sibling nodes, most recent first
+    pub killers: [BitPackedMove; 2],
+    /// The move skipped by an exclusion search of this node, the null move when there is none
+    pub excluded_move: BitPackedMove,
+}
+
+impl SearchStackEntry {
+    pub fn new() -> SearchStackEntry {
+        SearchStackEntry {
+            current_move: BitPackedMove::default(),
+            static_eval: NO_STATIC_EVAL,
+            killers: [BitPackedMove::default(); 2],
+            excluded_move: BitPackedMove::default(),
+        }
+    }
+}
+
+impl Default for SearchStackEntry {
+    fn default() -> SearchStackEntry {
+        SearchStackEntry::new()
+    }
+}
+
+/*
+   SearchStack
+   -----------
+   One entry per ply of the search path, indexed by the distance from the root, so that a node
+   can see what the nodes above it did: the moves that led to it, whether the static evaluation
+   improved since the same side last moved, and the killers of its siblings.
+*/
+pub struct SearchStack {
+    entries: Vec<SearchStackEntry>,
+}
+
+impl SearchStack {
+    pub fn new() -> SearchStack {
+        SearchStack {
+            entries: vec![SearchStackEntry::new(); MAX_PLY],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(SearchStackEntry::new());
+    }
+
+    /// Returns the moves played one and two plies before the given ply, null moves when there
+    /// were none.
+    pub fn get_previous_moves(&self, ply: usize) -> [BitPackedMove; 2] {
+        [
+            if ply >= 1 {
+                self.entries[ply - 1].current_move
+            } else {
+                BitPackedMove::default()
+            },
+            if ply >= 2 {
+                self.entries[ply - 2].current_move
+            } else {
+                BitPackedMove::default()
+            },
+        ]
+    }
+
+    /// Remembers a quiet move that caused a cutoff at the given ply.
+    pub fn add_killer(&mut self, ply: usize, m: BitPackedMove) {
+        let killers = &mut self.entries[ply].killers;
+        if killers[0] != m {
+            killers[1] = killers[0];
+            killers[0] = m;
+        }
+    }
+}
+
+impl Default for SearchStack {
+    fn default() -> SearchStack {
+        SearchStack::new()
+    }
+}
+
+impl Index<usize> for SearchStack {
+    type Output = SearchStackEntry;
+
+    fn index(&self, ply: usize) -> &SearchStackEntry {
+        &self.entries[ply]
+    }
+}
+
+impl IndexMut<usize> for SearchStack {
+    fn index_mut(&mut self, ply: usize) -> &mut SearchStackEntry {
+        &mut self.entries[ply]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SearchStack;
+    use crate::chess::{_move::BitPackedMove, piece::Piece, square::Square};
+
+    #[test]
+    fn keeps_previous_moves_and_killers_by_ply() {
+        let mut stack = SearchStack::new();
+        let e4 = BitPackedMove::new(Square::E2, Square::E4, Piece::WhitePawn);
+        let e5 = BitPackedMove::new(Square::E7, Square::E5, Piece::BlackPawn);
+        let nf3 = BitPackedMove::new(Square::G1, Square::F3, Piece::WhiteKnight);
+
+        stack[0].current_move = e4;
+        stack[1].current_move = e5;
+        assert_eq!(stack.get_previous_moves(2), [e5, e4]);
+        assert_eq!(stack.get_previous_moves(1), [e4, BitPackedMove::default()]);
+
+        stack.add_killer(2, e4);
+        stack.add_killer(2, nf3);
+        stack.add_killer(2, nf3);
+        assert_eq!(stack[2].killers, [nf3, e4]);
+
+        stack.clear();
+        assert_eq!(stack[2].killers, [BitPackedMove::default(); 2]);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::{
+    chess::{_move::BitPackedMove, color::Color},
+    search::{constants::*, options::SearchOptions},
+};
+
+/*
+   TimeManager
+   -----------
+   Decides how long a search may run. Every search gets two limits:
+
+   - The optimum time, after which no new iteration is started. It is scaled at every iteration
+     boundary, growing while the best move keeps changing or the score is dropping and
+     shrinking once the best move has been stable for a few iterations.
+   - The maximum time, after which the search is stopped wherever it is.
+
+   A new iteration is also not started when, judging by how long the last one took, it could
+   not finish before the maximum time.
+
+   All times are in milliseconds, and already account for the move overhead, the time lost
+   between the engine sending a move and the clock stopping.
+
+   Reading the clock at every node would be slow, so the search polls it every so many nodes.
+   That number follows the speed of the search, so the clock is read about every
+   `poll_interval` milliseconds on fast and slow machines alike.
+*/
+pub struct TimeManager {
+    /// Time to spend on a move when the search is stable, None when the search is not timed
+    pub optimum_time: Option<u128>,
+    /// Time after which the search must stop, None when the search is not timed
+    pub maximum_time: Option<u128>,
+    /// Time reserved for communication delays on every move
+    pub move_overhead: u32,
+    /// Milliseconds between polls of the clock during search
+    pub poll_interval: u64,
+    /// Nodes searched between polls of the clock, adapted to the speed of the search
+    nodes_between_polls: u64,
+
+    /// The number of consecutive iterations that ended with the same best move
+    best_move_stability: u32,
+    previous_best_move: Option<BitPackedMove>,
+    previous_score: Option<i32>,
+    /// Multiplier applied to the optimum time, updated at every iteration boundary
+    scale: f64,
+}
+
+impl TimeManager {
+    pub fn new() -> TimeManager {
+        TimeManager {
+            optimum_time: None,
+            maximum_time: None,
+            move_overhead: DEFAULT_MOVE_OVERHEAD,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            nodes_between_polls: MIN_NODES_BETWEEN_POLLS,
+            best_move_stability: 0,
+            previous_best_move: None,
+            previous_score: None,
+            scale: 1.0,
+        }
+    }
+
+    /// Sets the time limits for a new search with the given options, for the given side.
+    pub fn init(&mut self, options: &SearchOptions, side: Color) {
+        self.best_move_stability = 0;
+        self.previous_best_move = None;
+        self.previous_score = None;
+        self.scale = 1.0;
+        self.optimum_time = None;
+        self.maximum_time = None;
+
+        if options.infinite {
+            return;
+        }
+
+        if let Some(movetime) = options.movetime {
+            let time = movetime.saturating_sub(self.move_overhead).max(1) as u128;
+            self.optimum_time = Some(time);
+            self.maximum_time = Some(time);
+            return;
+        }
+
+        let (time_left, increment) = match side {
+            Color::White => (options.wtime, options.winc.unwrap_or(0)),
+            Color::Black => (options.btime, options.binc.unwrap_or(0)),
+        };
+        let time_left = match time_left {
+            Some(time_left) => time_left.saturating_sub(self.move_overhead) as u128,
+            None => return,
+        };
+        let increment = increment as u128;
+
+        let moves_to_go = match options.movestogo {
+            Some(moves_to_go) => moves_to_go.clamp(1, MAX_MOVES_TO_GO) as u128,
+            None => DEFAULT_MOVES_TO_GO as u128,
+        };
+
+        // Never plan to spend more than a fraction of the clock, unless this is the last move
+        // before the time control
+        let limit = if moves_to_go == 1 {
+            time_left * 9 / 10
+        } else {
+            time_left / 2
+        };
+
+        let optimum = time_left / moves_to_go + increment * 3 / 4;
+        let maximum = optimum * MAXIMUM_TIME_FACTOR;
+
+        self.optimum_time = Some(optimum.min(limit).max(1));
+        self.maximum_time = Some(maximum.min(limit).max(1));
+    }
+
+    pub fn get_nodes_between_polls(&self) -> u64 {
+        self.nodes_between_polls
+    }
+
+    /// Sets the number of nodes until the next poll of the clock from the speed of the search
+    /// so far, given the nodes searched and the time elapsed.
+    pub fn adapt_poll_interval(&mut self, nodes: u64, elapsed: u128) {
+        if elapsed == 0 {
+            return;
+        }
+
+        let nodes_per_ms = nodes / elapsed as u64;
+        self.nodes_between_polls = (nodes_per_ms * self.poll_interval)
+            .clamp(MIN_NODES_BETWEEN_POLLS, MAX_NODES_BETWEEN_POLLS);
+    }
+
+    /// Returns true once the search has used up its maximum time and must stop.
+    pub fn is_time_up(&self, elapsed: u128) -> bool {
+        match self.maximum_time {
+            Some(maximum_time) => elapsed >= maximum_time,
+            None => false,
+        }
+    }
+
+    /// Records the result of a completed iteration and rescales the optimum time with it.
+    pub fn update(&mut self, best_move: BitPackedMove, score: i32) {
+        if self.previous_best_move == Some(best_move) {
+            self.best_move_stability += 1;
+        } else {
+            self.best_move_stability = 0;
+        }
+
+        let stability_scale = match self.best_move_stability {
+            0 => 1.4,
+            1 => 1.2,
+            2 => 1.0,
+            3 => 0.9,
+            _ => 0.8,
+        };
+
+        // Spend more time when the score drops, to find a way out
+        let score_drop = match self.previous_score {
+            Some(previous_score) => (previous_score - score).clamp(0, 100),
+            None => 0,
+        };
+        let score_scale = 1.0 + score_drop as f64 / 200.0;
+
+        self.scale = stability_scale * score_scale;
+        self.previous_best_move = Some(best_move);
+        self.previous_score = Some(score);
+    }
+
+    /// Returns true if another iteration should be started, given the time elapsed so far and
+    /// the time the last iteration took.
+    pub fn should_start_iteration(&self, elapsed: u128, last_iteration_time: u128) -> bool {
+        let (optimum_time, maximum_time) = match (self.optimum_time, self.maximum_time) {
+            (Some(optimum_time), Some(maximum_time)) => (optimum_time, maximum_time),
+            _ => return true,
+        };
+
+        let soft_limit = ((optimum_time as f64 * self.scale) as u128).min(maximum_time);
+        if elapsed >= soft_limit {
+            return false;
+        }
+
+        // The next iteration takes a few times longer than the last one
+        elapsed + last_iteration_time * ITERATION_TIME_GROWTH < maximum_time
+    }
+}
+
+impl Default for TimeManager {
+    fn default() -> TimeManager {
+        TimeManager::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TimeManager;
+    use crate::{
+        chess::{_move::BitPackedMove, color::Color, piece::Piece, square::Square},
+        search::{constants::MIN_NODES_BETWEEN_POLLS, options::SearchOptions},
+    };
+
+    #[test]
+    fn allocates_within_the_clock_and_scales_with_stability() {
+        let mut time_manager = TimeManager::new();
+        time_manager.move_overhead = 50;
+
+        // Under the overhead there is still something left to search with
+        let mut options = SearchOptions::new();
+        options.wtime = Some(30);
+        time_manager.init(&options, Color::White);
+        assert_eq!(time_manager.maximum_time, Some(1));
+
+        // The last move before the time control may use most of the clock
+        options.wtime = Some(10_050);
+        options.movestogo = Some(1);
+        time_manager.init(&options, Color::White);
+        assert_eq!(time_manager.maximum_time, Some(9_000));
+
+        options.movestogo = None;
+        options.btime = Some(60_050);
+        options.binc = Some(1_000);
+        time_manager.init(&options, Color::Black);
+        let optimum = time_manager.optimum_time.unwrap();
+        let maximum = time_manager.maximum_time.unwrap();
+        assert!(optimum > 2_000 && optimum < maximum && maximum <= 30_000);
+
+        // An unstable best move earns more time than a stable one
+        let a = BitPackedMove::new(Square::E2, Square::E4, Piece::WhitePawn);
+        let b = BitPackedMove::new(Square::D2, Square::D4, Piece::WhitePawn);
+        time_manager.update(a, 20);
+        time_manager.update(b, 20);
+        assert!(time_manager.should_start_iteration(optimum, 0));
+
+        for _ in 0..5 {
+            time_manager.update(b, 20);
+        }
+        assert!(!time_manager.should_start_iteration(optimum, 0));
+
+        // No iteration is started that could not finish in time
+        assert!(!time_manager.should_start_iteration(optimum / 2, maximum / 2));
+    }
+
+    #[test]
+    fn poll_interval_follows_search_speed() {
+        let mut time_manager = TimeManager::new();
+        time_manager.poll_interval = 5;
+
+        // A million nodes per second is a thousand per millisecond
+        time_manager.adapt_poll_interval(1_000_000, 1_000);
+        assert_eq!(time_manager.get_nodes_between_polls(), 5_000);
+
+        // Slow searches still don't poll the clock at every node
+        time_manager.adapt_poll_interval(10, 1_000);
+        assert_eq!(
+            time_manager.get_nodes_between_polls(),
+            MIN_NODES_BETWEEN_POLLS
+        );
+    }
+}
@@ -1,7 +1,13 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+};
+
 use crate::{
-    board::{Board, Position},
+    board::{constants::ZOBRIST_SEED, Board, Position},
     chess,
     movegen::MoveGenerator,
+    search::constants::MATE_IN_MAX_PLY,
 };
 
 /*
@@ -10,95 +16,191 @@ use crate::{
    A transposition table is a hash table that stores information about positions that have already been searched.
    This allows the engine to avoid searching the same position multiple times, and can also be used to detect
    repetitions.
+
+   The table is made of buckets the size of a cache line, each holding `ENTRIES_PER_BUCKET` entries, so a probe
+   only ever touches one cache line. The high bits of a key select the bucket and its low 16 bits are stored in
+   the entry to tell positions sharing a bucket apart. Scores are stored in 16 bits too, which keeps an entry
+   to 12 bytes.
 */
 pub struct TranspositionTable {
     /// The actual hash table.
-    table: Vec<TranspositionTableEntry>,
-
-    /// The number of entries in the table.
-    size: u64,
+    table: Vec<TranspositionTableBucket>,
 
-    /// The age of the table. This is used to determine which entries to replace.
+    /// The generation of the table, incremented every search. Entries from older generations
+    /// are replaced first.
     age: u8,
 
-    /// The size of the table.
+    /// The number of buckets in the table.
     pub hash_size: usize,
 }
 
+pub const ENTRIES_PER_BUCKET: usize = 5;
+
+/// Entry ages wrap around, they only share a byte with the flag
+const AGE_MASK: u8 = 0x3F;
+
+/// How many plies of depth an entry is worth per search it has survived when choosing which one to replace
+const AGE_REPLACEMENT_WEIGHT: i32 = 8;
+
+/// An entry is only replaced by a bound for the same position from a search at most this many plies
+/// shallower, so quiescence results don't wipe out deep entries
+const SAME_POSITION_DEPTH_MARGIN: i32 = 3;
+
+/// Identifies a file written by `TranspositionTable::save_to`
+const HASH_FILE_MAGIC: [u8; 4] = *b"RTTT";
+
+/// Bumped whenever the layout of a saved table changes
+const HASH_FILE_VERSION: u32 = 2;
+
+/// Bytes of the header of a saved table: magic, version, zobrist seed, bucket size, bucket count
+/// and age
+const HASH_FILE_HEADER_SIZE: u64 = 4 + 4 + 4 + 4 + 8 + 1;
+
+/// Bytes of an entry in a saved table
+const HASH_FILE_ENTRY_SIZE: u64 = 12;
+
+#[derive(Clone, Copy)]
+#[repr(C, align(64))]
+pub struct TranspositionTableBucket {
+    entries: [TranspositionTableEntry; ENTRIES_PER_BUCKET],
+}
+
 impl TranspositionTable {
     pub fn new(hash_size_in_mb: usize) -> TranspositionTable {
-        let hash_size =
-            hash_size_in_mb * 1024 * 1024 / std::mem::size_of::<TranspositionTableEntry>();
+        let hash_size = (hash_size_in_mb * 1024 * 1024
+            / std::mem::size_of::<TranspositionTableBucket>())
+        .max(1);
         return TranspositionTable {
-            table: vec![TranspositionTableEntry::new(); hash_size],
-            size: 0,
+            table: vec![
+                TranspositionTableBucket {
+                    entries: [TranspositionTableEntry::new(); ENTRIES_PER_BUCKET]
+                };
+                hash_size
+            ],
             age: 0,
             hash_size,
         };
     }
 
+    /// Returns the memory used by the table, rounded to megabytes.
+    pub fn get_size_in_mb(&self) -> usize {
+        (self.hash_size * std::mem::size_of::<TranspositionTableBucket>() + (1 << 19)) >> 20
+    }
+
     pub fn increment_age(&mut self) {
-        self.age += 1;
+        self.age = (self.age + 1) & AGE_MASK;
+    }
+
+    /// Converts a score relative to the root into one relative to the position being stored, so
+    /// that a mate found through a transposition reports its distance from where it is probed.
+    pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+        if score >= MATE_IN_MAX_PLY {
+            score + ply as i32
+        } else if score <= -MATE_IN_MAX_PLY {
+            score - ply as i32
+        } else {
+            score
+        }
+    }
+
+    /// Converts a stored score back into one relative to the root, see `score_to_tt`.
+    pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+        if score >= MATE_IN_MAX_PLY {
+            score - ply as i32
+        } else if score <= -MATE_IN_MAX_PLY {
+            score + ply as i32
+        } else {
+            score
+        }
     }
 
     /// Clears the transposition table
     pub fn clear(&mut self) {
-        self.table.fill(TranspositionTableEntry::new());
-        self.size = 0;
+        for bucket in self.table.iter_mut() {
+            bucket.entries = [TranspositionTableEntry::new(); ENTRIES_PER_BUCKET];
+        }
         self.age = 0;
     }
 
-    /// Stores a new entry in the transposition table. If the entry already exists, it is overwritten.
+    /// Returns the index of the bucket for the given key, scaling the key onto the table so
+    /// that any table size can be used.
+    fn get_bucket_index(&self, key: u64) -> usize {
+        ((key as u128 * self.hash_size as u128) >> 64) as usize
+    }
+
+    /// Stores a new entry in the transposition table. An entry for the same position is
+    /// overwritten by exact scores, by searches of about the same depth or deeper, and when it
+    /// is from an earlier search. Otherwise the entry of the bucket with the lowest depth,
+    /// discounted by how many searches ago it was stored, is replaced.
+    /// Mate scores must be made relative to the position first, see `score_to_tt`.
     pub fn save(
         &mut self,
         key: u64,
         depth: u8,
         flag: TranspositionTableEntryFlag,
         value: i32,
+        static_eval: i32,
         m: chess::_move::BitPackedMove,
     ) {
-        let hash_index = key as usize & (self.hash_size - 1);
-
-        /*
-                      score (25)        | flag (2) |  depth (8) |            move (30)
-           00000000 00000000 00000000 0 |    00    |  00000000  | 00000000 00000000 0000000 00000
-        */
-        let data = ((value + 50_000) as u64)
-            | ((flag as u64) << 25)
-            | ((depth as u64) << 27)
-            | ((m.move_bits as u64) << 35);
-
-        let mut replace = false;
-
-        if self.table[hash_index].key == 0 {
-            self.size += 1;
-            replace = true;
-        } else {
-            if self.table[hash_index].age < self.age || self.table[hash_index].get_depth() <= depth
-            {
-                replace = true;
+        let age = self.age;
+        let bucket_index = self.get_bucket_index(key);
+        let entries = &mut self.table[bucket_index].entries;
+
+        let mut replace_index = 0;
+        let mut lowest_worth = i32::MAX;
+        for (i, entry) in entries.iter().enumerate() {
+            if entry.is_valid() && entry.matches(key) {
+                let is_shallower =
+                    (depth as i32) < entry.get_depth() as i32 - SAME_POSITION_DEPTH_MARGIN;
+                if is_shallower
+                    && flag != TranspositionTableEntryFlag::EXACT
+                    && entry.get_age() == age
+                {
+                    return;
+                }
+                replace_index = i;
+                break;
+            }
+            if !entry.is_valid() {
+                replace_index = i;
+                break;
             }
-        }
 
-        if !replace {
-            return;
+            let relative_age = (age.wrapping_sub(entry.get_age()) & AGE_MASK) as i32;
+            let worth = entry.get_depth() as i32 - AGE_REPLACEMENT_WEIGHT * relative_age;
+            if worth < lowest_worth {
+                lowest_worth = worth;
+                replace_index = i;
+            }
         }
 
-        self.table[hash_index] = TranspositionTableEntry {
-            key: key ^ data,
-            data,
-            age: self.age,
-        }
+        // Keep the old move when overwriting a position without a new one
+        let old_entry = entries[replace_index];
+        let m = if m == chess::_move::BitPackedMove::default() && old_entry.matches(key) {
+            old_entry.get_move()
+        } else {
+            m
+        };
+
+        entries[replace_index] =
+            TranspositionTableEntry::new_with(key, depth, flag, value, static_eval, m, age);
+    }
+
+    /// Returns the entry for the given key if there is one, whatever its depth and bound.
+    pub fn probe(&self, key: u64) -> Option<TranspositionTableEntry> {
+        self.table[self.get_bucket_index(key)]
+            .entries
+            .iter()
+            .find(|entry| entry.is_valid() && entry.matches(key))
+            .copied()
     }
 
     /// Returns the entry if it exists, otherwise returns None.
     pub fn get(&self, key: u64) -> Option<TranspositionTableEntry> {
-        let entry: TranspositionTableEntry = self.table[key as usize & (self.hash_size - 1)];
-        if entry.key == (key ^ entry.data) && entry.get_flag() == TranspositionTableEntryFlag::EXACT
-        {
-            return Some(entry);
+        match self.probe(key) {
+            Some(entry) if entry.get_flag() == TranspositionTableEntryFlag::EXACT => Some(entry),
+            _ => None,
         }
-        return None;
     }
 
     /// Returns the entry if it exists and is suitable for the given lower and upper bound, otherwise returns None.
@@ -108,22 +210,18 @@ impl TranspositionTable {
         depth: u8,
         alpha: i32,
         beta: i32,
+        ply: usize,
     ) -> TranspositionTableEntry {
-        let entry: TranspositionTableEntry = self.table[key as usize & (self.hash_size - 1)];
-
-        if entry.key == (key ^ entry.data) {
+        if let Some(entry) = self.probe(key) {
             if entry.get_depth() >= depth {
+                let value = entry.get_value(ply);
                 if entry.get_flag() == TranspositionTableEntryFlag::EXACT {
                     return entry;
                 }
-                if entry.get_flag() == TranspositionTableEntryFlag::ALPHA
-                    && entry.get_value() <= alpha
-                {
+                if entry.get_flag() == TranspositionTableEntryFlag::ALPHA && value <= alpha {
                     return entry;
                 }
-                if entry.get_flag() == TranspositionTableEntryFlag::BETA
-                    && entry.get_value() >= beta
-                {
+                if entry.get_flag() == TranspositionTableEntryFlag::BETA && value >= beta {
                     return entry;
                 }
             }
@@ -132,23 +230,136 @@ impl TranspositionTable {
         return TranspositionTableEntry::new();
     }
 
-    /// Returns how full the table is, as a number between 0 and 1000.
+    /// Returns how full the table is, as a number between 0 and 1000, by sampling the entries
+    /// of the first 1000 buckets that were stored during the current search.
     pub fn get_hashfull(&self) -> u32 {
-        return (self.size as f32 / self.hash_size as f32 * 1000.0) as u32;
+        let sampled = &self.table[..self.hash_size.min(1000)];
+        let used = sampled
+            .iter()
+            .flat_map(|bucket| bucket.entries.iter())
+            .filter(|entry| entry.is_valid() && entry.get_age() == self.age)
+            .count();
+
+        (used * 1000 / (sampled.len() * ENTRIES_PER_BUCKET)) as u32
+    }
+
+    /*
+       Saved tables start with a header, followed by every entry in bucket order:
+
+       magic (4) | version (4) | zobrist seed (4) | entries per bucket (4) | buckets (8) | age (1)
+       key (2) | depth (1) | age and flag (1) | move (4) | score (2) | static eval (2)
+
+       All numbers are little endian.
+    */
+
+    /// Writes the table to a file so that a later session can continue from it.
+    pub fn save_to(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(&HASH_FILE_MAGIC)?;
+        writer.write_all(&HASH_FILE_VERSION.to_le_bytes())?;
+        writer.write_all(&ZOBRIST_SEED.to_le_bytes())?;
+        writer.write_all(&(ENTRIES_PER_BUCKET as u32).to_le_bytes())?;
+        writer.write_all(&(self.hash_size as u64).to_le_bytes())?;
+        writer.write_all(&[self.age])?;
+
+        for entry in self.table.iter().flat_map(|bucket| bucket.entries.iter()) {
+            writer.write_all(&entry.key.to_le_bytes())?;
+            writer.write_all(&[entry.depth, entry.age_flag])?;
+            writer.write_all(&entry.move_bits.to_le_bytes())?;
+            writer.write_all(&entry.value.to_le_bytes())?;
+            writer.write_all(&entry.static_eval.to_le_bytes())?;
+        }
+
+        writer.flush()
+    }
+
+    /// Replaces the table with one written by `save_to`, taking on its size. Files from another
+    /// version of the format or hashed with different zobrist keys are rejected and leave the
+    /// table untouched.
+    pub fn load_from(&mut self, path: &str) -> io::Result<()> {
+        let file = File::open(path)?;
+        let file_size = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != HASH_FILE_MAGIC {
+            return Err(invalid("not a saved transposition table"));
+        }
+        if read_u32(&mut reader)? != HASH_FILE_VERSION {
+            return Err(invalid("unsupported transposition table version"));
+        }
+        if read_u32(&mut reader)? != ZOBRIST_SEED {
+            return Err(invalid(
+                "transposition table was saved with different zobrist keys",
+            ));
+        }
+        if read_u32(&mut reader)? as usize != ENTRIES_PER_BUCKET {
+            return Err(invalid("transposition table has a different bucket size"));
+        }
+
+        let mut buffer = [0u8; 8];
+        reader.read_exact(&mut buffer)?;
+        let hash_size = u64::from_le_bytes(buffer) as usize;
+        if hash_size == 0 {
+            return Err(invalid("transposition table is empty"));
+        }
+
+        // Check the bucket count against the file before allocating for it
+        let expected_size = (hash_size as u64)
+            .checked_mul(ENTRIES_PER_BUCKET as u64 * HASH_FILE_ENTRY_SIZE)
+            .and_then(|size| size.checked_add(HASH_FILE_HEADER_SIZE));
+        if expected_size != Some(file_size) {
+            return Err(invalid(
+                "transposition table size does not match the file length",
+            ));
+        }
+
+        let mut age = [0u8; 1];
+        reader.read_exact(&mut age)?;
+
+        let mut table = vec![
+            TranspositionTableBucket {
+                entries: [TranspositionTableEntry::new(); ENTRIES_PER_BUCKET]
+            };
+            hash_size
+        ];
+        let mut entry_bytes = [0u8; HASH_FILE_ENTRY_SIZE as usize];
+        for bucket in table.iter_mut() {
+            for entry in bucket.entries.iter_mut() {
+                reader.read_exact(&mut entry_bytes)?;
+                let field = |i: usize| [entry_bytes[i], entry_bytes[i + 1]];
+
+                *entry = TranspositionTableEntry {
+                    key: u16::from_le_bytes(field(0)),
+                    depth: entry_bytes[2],
+                    age_flag: entry_bytes[3],
+                    move_bits: u32::from_le_bytes(entry_bytes[4..8].try_into().unwrap()),
+                    value: i16::from_le_bytes(field(8)),
+                    static_eval: i16::from_le_bytes(field(10)),
+                };
+            }
+        }
+
+        self.table = table;
+        self.hash_size = hash_size;
+        self.age = age[0] & AGE_MASK;
+
+        Ok(())
     }
 
-    /// Returns the principal variation line for the given position.
+    /// Returns the principal variation line for the given position. The line ends at the first
+    /// move that is not legal, which an entry of another position sharing its key can hold.
     pub fn get_pv_line(&self, position: &mut Position) -> Vec<TranspositionTableEntry> {
         let mut pv_line: Vec<TranspositionTableEntry> = Vec::new();
         let mut positions_encountered: Vec<u64> = Vec::new();
 
-        loop {
-            let entry: TranspositionTableEntry =
-                self.table[position.hash as usize & (self.hash_size - 1)];
-
-            if entry.key != (position.hash ^ entry.data)
-                || entry.get_move() == chess::_move::BitPackedMove::default()
+        while let Some(entry) = self.probe(position.hash) {
+            if entry.get_move() == chess::_move::BitPackedMove::default()
                 || pv_line.len() > 64
+                || !position.generate_legal_moves().contains(&entry.get_move())
             {
                 break;
             }
@@ -157,7 +368,7 @@ impl TranspositionTable {
             pv_line.push(entry);
             position.make_move(entry.get_move(), false);
 
-            if position.is_in_check() && position.generate_legal_moves().len() == 0 {
+            if position.is_in_check() && position.generate_legal_moves().is_empty() {
                 break;
             }
         }
@@ -170,63 +381,111 @@ impl TranspositionTable {
     }
 }
 
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buffer = [0u8; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
 #[derive(Clone, Copy)]
 pub struct TranspositionTableEntry {
-    key: u64,
-    data: u64,
-    pub age: u8,
+    key: u16,
+    depth: u8,
+    age_flag: u8,
+    move_bits: u32,
+    value: i16,
+    static_eval: i16,
 }
 
 /*
    TranspositionTableEntry
    -----------------------
    A transposition table entry is a single entry in the transposition table. It contains the following information:
-   - The key, which is the hash of the position XORed with the data.
-   - The data, which contains the following information:
-     - The score, which is the evaluation of the position.
+   - The key, the low 16 bits of the hash of the position, to verify the entry belongs to the position probed.
+   - The depth, which is the depth of the search that produced the score.
+   - The age and the flag packed into one byte:
+     - The age, the generation of the table when the entry was stored, used to determine which entries to replace.
      - The flag, which is the type of score (exact, alpha, beta, or null).
-     - The depth, which is the depth of the search that produced the score.
-     - The move, which is the move that produced the score.
-   - The age, which is used to determine which entries to replace.
+   - The move, which is the move that produced the score.
+   - The score, which is the evaluation of the position, with mate scores relative to the position itself.
+   - The static evaluation of the position.
 
-   When retrieving an entry from the transposition table, the key is XORed with the data to ensure that the entry
-    is valid.
+     age (6) | flag (2)
+      000000 |    00
 */
 impl TranspositionTableEntry {
     pub fn new() -> TranspositionTableEntry {
         return TranspositionTableEntry {
             key: 0,
-            data: 0,
-            age: 0,
+            depth: 0,
+            age_flag: TranspositionTableEntryFlag::NULL as u8,
+            move_bits: 0,
+            value: 0,
+            static_eval: 0,
         };
     }
+
+    pub fn new_with(
+        key: u64,
+        depth: u8,
+        flag: TranspositionTableEntryFlag,
+        value: i32,
+        static_eval: i32,
+        m: chess::_move::BitPackedMove,
+        age: u8,
+    ) -> TranspositionTableEntry {
+        TranspositionTableEntry {
+            key: key as u16,
+            depth,
+            age_flag: ((age & AGE_MASK) << 2) | flag as u8,
+            move_bits: m.move_bits,
+            value: value as i16,
+            static_eval: static_eval as i16,
+        }
+    }
 }
 
 impl TranspositionTableEntry {
+    /// Returns whether the entry belongs to the position with the given hash.
+    pub fn matches(&self, key: u64) -> bool {
+        self.key == key as u16
+    }
+
     /// Returns the depth of the search that produced the score.
     pub fn get_depth(&self) -> u8 {
-        return (self.data >> 27) as u8 & 0xF;
+        return self.depth;
     }
 
     /// Returns the type of score (exact, alpha, beta, or null).
     pub fn get_flag(&self) -> TranspositionTableEntryFlag {
-        return TranspositionTableEntryFlag::from_u8((self.data >> 25) as u8 & 0x3);
+        return TranspositionTableEntryFlag::from_u8(self.age_flag & 0x3);
+    }
+
+    /// Returns the generation of the table when the entry was stored.
+    pub fn get_age(&self) -> u8 {
+        self.age_flag >> 2
     }
 
     /// Returns whether the entry is valid.
     pub fn is_valid(&self) -> bool {
-        return self.key != 0;
+        return self.get_flag() != TranspositionTableEntryFlag::NULL;
+    }
+
+    /// Returns the evaluation of the position, with mate scores relative to the root given the
+    /// ply the position is probed at.
+    pub fn get_value(&self, ply: usize) -> i32 {
+        TranspositionTable::score_from_tt(self.value as i32, ply)
     }
 
-    /// Returns the evaluation of the position.
-    pub fn get_value(&self) -> i32 {
-        return ((self.data as i32 & 0x1FFFFFF) - 50_000) as i32;
+    /// Returns the static evaluation of the position.
+    pub fn get_static_eval(&self) -> i32 {
+        self.static_eval as i32
     }
 
     /// Returns the move that produced the score.
     pub fn get_move(&self) -> chess::_move::BitPackedMove {
         return chess::_move::BitPackedMove {
-            move_bits: (self.data >> 35) as u32,
+            move_bits: self.move_bits,
         };
     }
 }
@@ -258,3 +517,261 @@ impl TranspositionTableEntryFlag {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{
+        TranspositionTable, TranspositionTableBucket, TranspositionTableEntry,
+        TranspositionTableEntryFlag, ENTRIES_PER_BUCKET,
+    };
+    use crate::{
+        board::{Board, Position},
+        chess::{_move::BitPackedMove, constants::STARTING_FEN, piece::Piece, square::Square},
+        search::constants::{INFINITY, MATE},
+    };
+
+    #[test]
+    fn entry_round_trips_every_field() {
+        let mut m = BitPackedMove::new(Square::H2, Square::G1, Piece::BlackPawn);
+        m.set_capture(Piece::WhiteKnight);
+        m.set_promotion(Piece::BlackQueen);
+
+        for &(depth, flag, value, static_eval, age) in &[
+            (0, TranspositionTableEntryFlag::EXACT, 0, 0, 0),
+            (17, TranspositionTableEntryFlag::BETA, -1234, 56, 3),
+            (255, TranspositionTableEntryFlag::ALPHA, MATE, -MATE, 63),
+        ] {
+            let key = 0x9E37_79B9_7F4A_7C15;
+            let entry =
+                TranspositionTableEntry::new_with(key, depth, flag, value, static_eval, m, age);
+
+            assert!(entry.matches(key));
+            assert!(!entry.matches(key ^ 1));
+            assert_eq!(entry.get_move(), m);
+            assert!(entry.get_flag() == flag);
+            assert_eq!(entry.get_depth(), depth);
+            assert_eq!(entry.get_age(), age);
+            assert_eq!(entry.get_value(0), value);
+            assert_eq!(entry.get_static_eval(), static_eval);
+        }
+    }
+
+    #[test]
+    fn mate_scores_are_stored_relative_to_the_position() {
+        let mut tt = TranspositionTable::new(1);
+        let key = 12345;
+
+        // Mated three plies below a position found at ply 5
+        let score = -MATE + 8;
+        tt.save(
+            key,
+            4,
+            TranspositionTableEntryFlag::EXACT,
+            TranspositionTable::score_to_tt(score, 5),
+            0,
+            BitPackedMove::default(),
+        );
+
+        // Reached through a transposition at ply 2 the mate is three plies closer to the root
+        let entry = tt.probe_entry(key, 4, -INFINITY, INFINITY, 2);
+        assert_eq!(entry.get_value(2), -MATE + 5);
+        assert_eq!(entry.get_value(5), score);
+
+        // Ordinary scores are unaffected
+        tt.save(
+            key,
+            4,
+            TranspositionTableEntryFlag::EXACT,
+            TranspositionTable::score_to_tt(150, 5),
+            0,
+            BitPackedMove::default(),
+        );
+        assert_eq!(
+            tt.probe_entry(key, 4, -INFINITY, INFINITY, 2).get_value(2),
+            150
+        );
+    }
+
+    /// Returns a key that maps to the given bucket, with the given verification bits
+    fn key_for_bucket(tt: &TranspositionTable, bucket: usize, low: u16) -> u64 {
+        let start = ((bucket as u128) << 64).div_ceil(tt.hash_size as u128) as u64;
+        (start & !0xFFFF) + (1 << 16) + low as u64
+    }
+
+    #[test]
+    fn shallow_bounds_keep_deep_entries_of_the_same_position() {
+        let mut tt = TranspositionTable::new(1);
+        let key = 0x1234_5678;
+        let m = BitPackedMove::new(Square::E2, Square::E4, Piece::WhitePawn);
+
+        tt.save(key, 10, TranspositionTableEntryFlag::BETA, 50, 0, m);
+
+        // A quiescence bound leaves the deep entry alone
+        tt.save(key, 0, TranspositionTableEntryFlag::ALPHA, -20, 0, m);
+        assert_eq!(tt.probe(key).unwrap().get_depth(), 10);
+
+        // A search of about the same depth replaces it, keeping the move when it has none
+        tt.save(
+            key,
+            8,
+            TranspositionTableEntryFlag::ALPHA,
+            -20,
+            0,
+            BitPackedMove::default(),
+        );
+        let entry = tt.probe(key).unwrap();
+        assert_eq!(entry.get_depth(), 8);
+        assert_eq!(entry.get_move(), m);
+
+        // So do exact scores and entries of the next search
+        tt.save(key, 2, TranspositionTableEntryFlag::EXACT, 30, 0, m);
+        assert_eq!(tt.probe(key).unwrap().get_depth(), 2);
+        tt.save(key, 10, TranspositionTableEntryFlag::BETA, 50, 0, m);
+        tt.increment_age();
+        tt.save(key, 0, TranspositionTableEntryFlag::ALPHA, -20, 0, m);
+        assert_eq!(tt.probe(key).unwrap().get_depth(), 0);
+    }
+
+    #[test]
+    fn buckets_replace_the_least_valuable_entry() {
+        // Five entries fill a cache line
+        assert_eq!(std::mem::size_of::<TranspositionTableEntry>(), 12);
+        assert_eq!(std::mem::size_of::<TranspositionTableBucket>(), 64);
+
+        let mut tt = TranspositionTable::new(1);
+        let m = BitPackedMove::default();
+        let keys: Vec<u64> = (1..=6).map(|i| key_for_bucket(&tt, 7, i)).collect();
+        assert!(keys.iter().all(|&key| tt.get_bucket_index(key) == 7));
+
+        for (&key, depth) in keys.iter().zip([10, 3, 8, 6, 5]) {
+            tt.save(key, depth, TranspositionTableEntryFlag::EXACT, 0, 0, m);
+        }
+
+        // The bucket is full, so the shallowest entry makes way
+        tt.save(keys[5], 1, TranspositionTableEntryFlag::EXACT, 0, 0, m);
+        assert!(tt.probe(keys[1]).is_none());
+        for &i in &[0, 2, 3, 4, 5] {
+            assert!(tt.probe(keys[i]).is_some());
+        }
+
+        // Entries from old searches are worth less than their depth
+        tt.clear();
+        tt.save(keys[0], 10, TranspositionTableEntryFlag::EXACT, 0, 0, m);
+        for _ in 0..3 {
+            tt.increment_age();
+        }
+        for &key in &keys[1..5] {
+            tt.save(key, 2, TranspositionTableEntryFlag::EXACT, 0, 0, m);
+        }
+        tt.save(keys[5], 1, TranspositionTableEntryFlag::EXACT, 0, 0, m);
+        assert!(tt.probe(keys[0]).is_none());
+        assert!(tt.probe(keys[5]).is_some());
+    }
+
+    #[test]
+    fn hashfull_counts_entries_of_the_current_search() {
+        let mut tt = TranspositionTable::new(1);
+        assert_eq!(tt.get_hashfull(), 0);
+
+        for bucket in 0..500 {
+            for i in 0..ENTRIES_PER_BUCKET {
+                let key = key_for_bucket(&tt, bucket, i as u16);
+                tt.save(
+                    key,
+                    1,
+                    TranspositionTableEntryFlag::EXACT,
+                    0,
+                    0,
+                    BitPackedMove::default(),
+                );
+            }
+        }
+        assert_eq!(tt.get_hashfull(), 500);
+
+        tt.increment_age();
+        assert_eq!(tt.get_hashfull(), 0);
+    }
+
+    #[test]
+    fn pv_line_stops_at_moves_that_are_not_legal() {
+        let mut tt = TranspositionTable::new(1);
+        let mut position = Position::new(Some(STARTING_FEN));
+        let e4 = BitPackedMove::new(Square::E2, Square::E4, Piece::WhitePawn);
+        tt.save(
+            position.hash,
+            2,
+            TranspositionTableEntryFlag::EXACT,
+            0,
+            0,
+            e4,
+        );
+
+        // An entry of another position with the same key, its move can't be played here
+        position.make_move(e4, false);
+        let after_e4 = position.hash;
+        position.unmake_move();
+        let knight_move = BitPackedMove::new(Square::C3, Square::D5, Piece::WhiteKnight);
+        tt.save(
+            after_e4,
+            1,
+            TranspositionTableEntryFlag::EXACT,
+            0,
+            0,
+            knight_move,
+        );
+
+        let pv_line = tt.get_pv_line(&mut position);
+        assert_eq!(pv_line.len(), 1);
+        assert_eq!(pv_line[0].get_move(), e4);
+        assert_eq!(position.hash, Position::new(Some(STARTING_FEN)).hash);
+    }
+
+    #[test]
+    fn saved_table_loads_back() {
+        let path = std::env::temp_dir().join(format!("redtail-tt-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut tt = TranspositionTable::new(1);
+        let m = BitPackedMove::new(Square::E2, Square::E4, Piece::WhitePawn);
+        tt.increment_age();
+        tt.save(42, 9, TranspositionTableEntryFlag::BETA, 120, -35, m);
+        tt.save_to(path).unwrap();
+
+        let mut loaded = TranspositionTable::new(2);
+        loaded.load_from(path).unwrap();
+        assert_eq!(loaded.hash_size, tt.hash_size);
+        assert_eq!(loaded.age, tt.age);
+
+        let entry = loaded.probe(42).unwrap();
+        assert_eq!(entry.get_move(), m);
+        assert_eq!(entry.get_depth(), 9);
+        assert!(entry.get_flag() == TranspositionTableEntryFlag::BETA);
+        assert_eq!(entry.get_value(0), 120);
+        assert_eq!(entry.get_static_eval(), -35);
+
+        // A file from another version is rejected without touching the table
+        let mut bytes = std::fs::read(path).unwrap();
+        bytes[4] ^= 0xFF;
+        std::fs::write(path, bytes).unwrap();
+        assert!(loaded.load_from(path).is_err());
+        assert!(loaded.probe(42).is_some());
+
+        // So is a truncated file, or one claiming more buckets than it holds
+        tt.save_to(path).unwrap();
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::write(path, &bytes[..bytes.len() - 1]).unwrap();
+        let error = loaded.load_from(path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut bytes = bytes;
+        bytes[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(path, bytes).unwrap();
+        let error = loaded.load_from(path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(loaded.probe(42).is_some());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
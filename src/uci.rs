@@ -11,6 +11,7 @@ use redtail::{
     board::{Board, Position},
     chess,
     movegen::MoveGenerator,
+    search::constants::{DEFAULT_MOVE_OVERHEAD, DEFAULT_POLL_INTERVAL},
     search::evaluate::*,
     search::options::*,
     search::utils::*,
@@ -22,6 +23,8 @@ pub struct UCI {
     transposition_table: tt::TranspositionTable,
     evaluator: Evaluator,
     stop_flag: Arc<AtomicBool>,
+    /// File used by the SaveHash and LoadHash options
+    hash_file: String,
 }
 
 impl UCI {
@@ -31,6 +34,7 @@ impl UCI {
             transposition_table: tt::TranspositionTable::new(2048),
             evaluator: Evaluator::new(),
             stop_flag: Arc::new(AtomicBool::new(false)),
+            hash_file: String::from("redtail.hash"),
         };
         uci.position
             .set_fen(String::from(chess::constants::STARTING_FEN));
@@ -79,6 +83,18 @@ impl UCI {
                     println!("id name redtail_vx");
                     println!("id author George T.G. Munyoro");
                     println!("option name Hash type spin default 1 min 1 max 1");
+                    println!(
+                        "option name Move Overhead type spin default {} min 0 max 5000",
+                        DEFAULT_MOVE_OVERHEAD
+                    );
+                    println!(
+                        "option name Poll Interval type spin default {} min 1 max 100",
+                        DEFAULT_POLL_INTERVAL
+                    );
+                    println!("option name UCI_ShowWDL type check default false");
+                    println!("option name HashFile type string default redtail.hash");
+                    println!("option name SaveHash type button");
+                    println!("option name LoadHash type button");
                     println!("uciok");
                     io::stdout().flush().unwrap();
                 }
@@ -92,6 +108,7 @@ impl UCI {
                     self.position
                         .set_fen(String::from(chess::constants::STARTING_FEN));
                     self.transposition_table.clear();
+                    self.evaluator.new_game();
                 }
 
                 "position" => self.handle_position(tokens),
@@ -110,10 +127,14 @@ impl UCI {
 
                 "draw" => self.position.draw(),
 
-                "setoption" => {}
+                "savehash" if tokens.len() > 1 => self.save_hash(&tokens[1..].join(" ")),
+
+                "loadhash" if tokens.len() > 1 => self.load_hash(&tokens[1..].join(" ")),
+
+                "setoption" => self.set_option(tokens),
 
                 _ => {
-                    println!("Unknown command: {}", buffer.trim());
+                    println!("info string unknown command: {}", buffer.trim());
                 }
             }
         }
@@ -151,6 +172,72 @@ impl UCI {
         }
     }
 
+    /// Handles `setoption name <id> [value <x>]`
+    fn set_option(&mut self, tokens: Vec<&str>) {
+        let value_index = tokens.iter().position(|&token| token == "value");
+        let name = match tokens.iter().position(|&token| token == "name") {
+            Some(i) => tokens[i + 1..value_index.unwrap_or(tokens.len())].join(" "),
+            None => return,
+        };
+        let value = value_index.map(|i| tokens[i + 1..].join(" "));
+
+        match name.to_lowercase().as_str() {
+            "move overhead" => {
+                if let Some(Ok(overhead)) = value.map(|value| value.parse::<u32>()) {
+                    self.evaluator.time_manager.move_overhead = overhead.min(5000);
+                }
+            }
+            "poll interval" => {
+                if let Some(Ok(interval)) = value.map(|value| value.parse::<u64>()) {
+                    self.evaluator.time_manager.poll_interval = interval.clamp(1, 100);
+                }
+            }
+            "uci_showwdl" => {
+                if let Some(value) = value {
+                    self.evaluator.show_wdl = value.eq_ignore_ascii_case("true");
+                }
+            }
+            "hashfile" => {
+                if let Some(value) = value {
+                    self.hash_file = value;
+                }
+            }
+            "savehash" => self.save_hash(&self.hash_file.clone()),
+            "loadhash" => self.load_hash(&self.hash_file.clone()),
+            _ => println!("info string unknown option: {}", name),
+        }
+    }
+
+    /// Writes the transposition table to the given file
+    fn save_hash(&self, path: &str) {
+        match self.transposition_table.save_to(path) {
+            Ok(()) => println!("info string saved hash to {}", path),
+            Err(e) => println!("info string could not save hash to {}: {}", path, e),
+        }
+        io::stdout().flush().unwrap();
+    }
+
+    /// Replaces the transposition table with the one saved in the given file
+    fn load_hash(&mut self, path: &str) {
+        let hash_size = self.transposition_table.get_size_in_mb();
+        match self.transposition_table.load_from(path) {
+            Ok(()) => {
+                println!("info string loaded hash from {}", path);
+
+                // The table takes the size it was saved with
+                if self.transposition_table.get_size_in_mb() != hash_size {
+                    println!(
+                        "info string hash size changed from {} to {} MB",
+                        hash_size,
+                        self.transposition_table.get_size_in_mb()
+                    );
+                }
+            }
+            Err(e) => println!("info string could not load hash from {}: {}", path, e),
+        }
+        io::stdout().flush().unwrap();
+    }
+
     /// Prints perft stats for the current position at the given depth
     fn perft(&mut self, tokens: Vec<&str>) {
         if tokens.len() < 2 {
@@ -159,9 +246,8 @@ impl UCI {
         let depth = tokens[1].parse::<u8>().unwrap();
         let start_time = std::time::Instant::now();
         let nodes = self.position.perft(depth);
-        let end_time = std::time::Instant::now();
-        let elapsed = end_time.duration_since(start_time);
-        let nps = nodes as f64 / (elapsed.as_millis() as f64 / 1000.0);
+        let elapsed = start_time.elapsed();
+        let nps = (nodes as f64 / elapsed.as_secs_f64().max(0.001)) as u64;
         println!("nodes {} nps {}", nodes, nps);
     }
 